@@ -0,0 +1,673 @@
+//! Command-line client for everyday bucket operations.
+//!
+//! Credentials are only read from the `B2_APPLICATION_KEY_ID` and `B2_APPLICATION_KEY` environment variables,
+//! or the official `b2` CLI's account info if neither is set. Remote files are given as `b2://bucket/path` URIs.
+
+use std::path::{Path, PathBuf};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use serde_json::json;
+use smol_str::SmolStr;
+use tokio::io::AsyncWriteExt;
+
+use yab2::bulk::DeleteOptions;
+use yab2::models::{capabilities::B2Capability, B2Bucket, B2BucketType, B2FileAction, B2FileInfo};
+use yab2::sync::{SyncAction, SyncDirection, SyncOptions};
+use yab2::*;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Parser)]
+#[command(name = "yab2", version, about = "Backblaze B2 command-line client")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List files under a `b2://bucket/prefix`.
+    Ls {
+        uri: String,
+
+        /// List every version of each file, including hide markers.
+        #[arg(long)]
+        versions: bool,
+
+        /// List files in all subfolders, instead of only the top level.
+        #[arg(short, long)]
+        recursive: bool,
+
+        /// Print each file as a line of JSON.
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Copy a local file to `b2://bucket/path`, or a remote file to a local path.
+    Cp {
+        src: String,
+        dst: String,
+
+        /// The content type of uploaded files, detected by B2 from the file extension if not set.
+        #[arg(long)]
+        content_type: Option<String>,
+    },
+
+    /// Delete the latest version of a remote file.
+    Rm {
+        uri: String,
+
+        /// Delete every version of the file.
+        #[arg(long)]
+        versions: bool,
+
+        /// Delete every version of every file under the prefix, including hide markers.
+        #[arg(short, long)]
+        recursive: bool,
+
+        /// Delete file versions protected by governance mode retention.
+        #[arg(long)]
+        bypass_governance: bool,
+    },
+
+    /// Hide a remote file.
+    Hide { uri: String },
+
+    /// Write a remote file to stdout.
+    Cat { uri: String },
+
+    /// Print information about the latest version of a remote file as JSON.
+    Stat { uri: String },
+
+    /// Manage buckets.
+    #[command(subcommand)]
+    Buckets(BucketsCommand),
+
+    /// Manage application keys.
+    #[command(subcommand)]
+    Keys(KeysCommand),
+
+    /// Sync a local directory with `b2://bucket/prefix`, in the direction of the arguments.
+    Sync {
+        src: String,
+        dst: String,
+
+        /// Delete files in the destination that do not exist in the source.
+        #[arg(long)]
+        delete: bool,
+
+        /// Print what would be done, without changing anything.
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Only sync files matching these glob patterns.
+        #[arg(long)]
+        include: Vec<String>,
+
+        /// Skip files matching these glob patterns.
+        #[arg(long)]
+        exclude: Vec<String>,
+
+        /// Compare files by SHA1 hash, instead of by size and modification time.
+        #[arg(long)]
+        compare_sha1: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum BucketsCommand {
+    /// Create a new bucket.
+    Create {
+        name: String,
+
+        /// Allow anyone to download files from the bucket.
+        #[arg(long)]
+        public: bool,
+    },
+
+    /// Change the type of a bucket.
+    Update {
+        name: String,
+
+        #[arg(long = "type", value_enum)]
+        bucket_type: BucketType,
+    },
+
+    /// List buckets.
+    List {
+        /// Print each bucket as a line of JSON.
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Delete an empty bucket.
+    Delete { name: String },
+}
+
+#[derive(Subcommand)]
+enum KeysCommand {
+    /// Create a new application key, printing its ID and secret.
+    Create {
+        name: String,
+
+        /// Comma-separated capabilities, such as `listFiles,readFiles`.
+        #[arg(long, value_delimiter = ',', required = true)]
+        capabilities: Vec<String>,
+
+        /// Restrict the key to a bucket, by name.
+        #[arg(long)]
+        bucket: Option<String>,
+
+        /// Restrict the key to files starting with this prefix. Requires `--bucket`.
+        #[arg(long, requires = "bucket")]
+        prefix: Option<String>,
+
+        /// Number of seconds until the key expires.
+        #[arg(long)]
+        duration: Option<u64>,
+    },
+
+    /// List application keys.
+    List,
+
+    /// Delete an application key by ID.
+    Delete { key_id: String },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+#[value(rename_all = "camelCase")]
+enum BucketType {
+    AllPublic,
+    AllPrivate,
+}
+
+/// A `b2://bucket/path` URI.
+struct Remote<'a> {
+    bucket: &'a str,
+    path: &'a str,
+}
+
+fn remote(uri: &str) -> Option<Remote<'_>> {
+    let rest = uri.strip_prefix("b2://")?;
+
+    let (bucket, path) = rest.split_once('/').unwrap_or((rest, ""));
+
+    Some(Remote { bucket, path })
+}
+
+fn require_remote(uri: &str) -> Result<Remote<'_>, Error> {
+    remote(uri).ok_or_else(|| format!("expected a b2://bucket/path URI, found {uri:?}").into())
+}
+
+async fn find_bucket(client: &Client, name: &str) -> Result<B2Bucket, Error> {
+    let buckets = client.list_buckets(&ListBuckets::builder().bucket_name(name).build()).await?;
+
+    buckets.into_iter().next().ok_or_else(|| format!("bucket {name:?} not found").into())
+}
+
+/// Lists versions of the file with exactly the given name, newest first.
+async fn find_versions(client: &Client, bucket_id: &str, name: &str, all: bool) -> Result<Vec<B2FileInfo>, Error> {
+    let mut files = Vec::new();
+
+    let (mut start_file_name, mut start_file_id) = (SmolStr::from(name), None::<SmolStr>);
+
+    loop {
+        let list = client
+            .list_files(
+                &ListFiles::builder()
+                    .all_versions(all)
+                    .bucket_id(bucket_id)
+                    .prefix(name)
+                    .start_file_name(start_file_name.as_str())
+                    .start_file_id(start_file_id.as_deref())
+                    .max_file_count(if all { 1000 } else { 1 })
+                    .build(),
+            )
+            .await?;
+
+        files.extend(list.files.into_iter().filter(|file| file.file_name == name));
+
+        match list.next_file_name {
+            Some(next) if all && next == name => (start_file_name, start_file_id) = (next, list.next_file_id),
+            _ => break,
+        }
+    }
+
+    match files.is_empty() {
+        true => Err(format!("file {name:?} not found").into()),
+        false => Ok(files),
+    }
+}
+
+fn action_name(action: &Option<B2FileAction>) -> &'static str {
+    match action {
+        None | Some(B2FileAction::Uploaded) => "upload",
+        Some(B2FileAction::Started) => "start",
+        Some(B2FileAction::Hidden) => "hide",
+        Some(B2FileAction::Folder) => "folder",
+    }
+}
+
+fn file_json(file: &B2FileInfo) -> serde_json::Value {
+    json!({
+        "fileName": file.file_name,
+        "fileId": file.file_id,
+        "action": action_name(&file.action),
+        "bucketId": file.bucket_id,
+        "contentLength": file.content_length,
+        "contentSha1": file.content_sha1,
+        "contentType": file.content_type,
+        "fileInfo": file.file_info,
+        "uploadTimestamp": file.upload_timestamp,
+    })
+}
+
+fn capability_names(capabilities: B2Capability) -> Vec<&'static str> {
+    B2Capability::ALL_CAPABILITIES_AND_NAMES
+        .iter()
+        .filter(|(capability, _)| capabilities.contains(*capability))
+        .map(|(_, name)| *name)
+        .collect()
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+
+    if let Err(e) = run(cli).await {
+        eprintln!("error: {e}");
+        std::process::exit(1);
+    }
+}
+
+async fn run(cli: Cli) -> Result<(), Error> {
+    // secrets are never accepted as arguments, where they would be visible to other processes
+    let builder = match [credentials::KEY_ID_ENV, credentials::KEY_ENV]
+        .iter()
+        .any(|name| std::env::var_os(name).is_some())
+    {
+        true => ClientBuilder::from_env()?,
+        false => ClientBuilder::from_account_info()?,
+    };
+
+    let client = builder.user_agent("yab2-cli").authorize().await?;
+
+    match cli.command {
+        Command::Ls {
+            uri,
+            versions,
+            recursive,
+            json,
+        } => ls(&client, &uri, versions, recursive, json).await,
+        Command::Cp { src, dst, content_type } => cp(&client, &src, &dst, content_type.as_deref()).await,
+        Command::Rm {
+            uri,
+            versions,
+            recursive,
+            bypass_governance,
+        } => rm(&client, &uri, versions, recursive, bypass_governance).await,
+        Command::Hide { uri } => {
+            let uri = require_remote(&uri)?;
+            let bucket = find_bucket(&client, uri.bucket).await?;
+
+            client.hide_file(Some(&bucket.bucket_id), uri.path).await?;
+
+            Ok(())
+        }
+        Command::Cat { uri } => {
+            let uri = require_remote(&uri)?;
+            let bucket = find_bucket(&client, uri.bucket).await?;
+            let file = find_versions(&client, &bucket.bucket_id, uri.path, false).await?;
+
+            download(&client, &file[0], tokio::io::stdout()).await
+        }
+        Command::Stat { uri } => {
+            let uri = require_remote(&uri)?;
+            let bucket = find_bucket(&client, uri.bucket).await?;
+            let file = find_versions(&client, &bucket.bucket_id, uri.path, false).await?;
+
+            println!("{:#}", file_json(&file[0]));
+
+            Ok(())
+        }
+        Command::Buckets(command) => buckets(&client, command).await,
+        Command::Keys(command) => keys(&client, command).await,
+        Command::Sync {
+            src,
+            dst,
+            delete,
+            dry_run,
+            include,
+            exclude,
+            compare_sha1,
+        } => {
+            let (direction, local, uri) = match (remote(&src), remote(&dst)) {
+                (None, Some(uri)) => (SyncDirection::Upload, &src, uri),
+                (Some(uri), None) => (SyncDirection::Download, &dst, uri),
+                _ => return Err("sync requires one local directory and one b2:// URI".into()),
+            };
+
+            let bucket = find_bucket(&client, uri.bucket).await?;
+
+            let options = SyncOptions::builder()
+                .direction(direction)
+                .bucket_id(bucket.bucket_id.as_str())
+                .delete(delete)
+                .dry_run(dry_run)
+                .compare_sha1(compare_sha1)
+                .include(include.iter().map(String::as_str).collect::<Vec<_>>())
+                .exclude(exclude.iter().map(String::as_str).collect::<Vec<_>>())
+                .build();
+
+            let report = client.sync(Path::new(local), uri.path, &options).await?;
+
+            for action in report.planned.iter().chain(&report.completed) {
+                println!("{}", describe(action));
+            }
+
+            for (failure, e) in &report.failed {
+                eprintln!("failed: {failure:?}: {e}");
+            }
+
+            match report.failed.is_empty() {
+                true => Ok(()),
+                false => Err(format!("{} sync actions failed", report.failed.len()).into()),
+            }
+        }
+    }
+}
+
+fn describe(action: &SyncAction) -> String {
+    match action {
+        SyncAction::Upload { path, file_name } => format!("upload {} -> {file_name}", path.display()),
+        SyncAction::Download { file, path } => format!("download {} -> {}", file.file_name, path.display()),
+        SyncAction::HideRemote { file_name } => format!("hide {file_name}"),
+        SyncAction::DeleteRemote { file_name, file_ids } => {
+            format!("delete {file_name} ({} versions)", file_ids.len())
+        }
+        SyncAction::DeleteLocal { path } => format!("delete {}", path.display()),
+    }
+}
+
+async fn ls(client: &Client, uri: &str, versions: bool, recursive: bool, json: bool) -> Result<(), Error> {
+    let uri = require_remote(uri)?;
+    let bucket = find_bucket(client, uri.bucket).await?;
+
+    let (mut start_file_name, mut start_file_id) = (None::<SmolStr>, None::<SmolStr>);
+
+    loop {
+        let list = client
+            .list_files(
+                &ListFiles::builder()
+                    .all_versions(versions)
+                    .bucket_id(bucket.bucket_id.as_str())
+                    .prefix((!uri.path.is_empty()).then_some(uri.path))
+                    .delimiter((!recursive).then_some("/"))
+                    .start_file_name(start_file_name.as_deref())
+                    .start_file_id(start_file_id.as_deref())
+                    .max_file_count(1000)
+                    .build(),
+            )
+            .await?;
+
+        for file in &list.files {
+            if json {
+                println!("{}", file_json(file));
+            } else if versions {
+                println!(
+                    "{}\t{}\t{}\t{}",
+                    action_name(&file.action),
+                    file.content_length,
+                    file.file_id,
+                    file.file_name
+                );
+            } else {
+                match file.action {
+                    Some(B2FileAction::Folder) => println!("{}", file.file_name),
+                    _ => println!("{}\t{}", file.content_length, file.file_name),
+                }
+            }
+        }
+
+        match list.next_file_name {
+            Some(next) => (start_file_name, start_file_id) = (Some(next), list.next_file_id),
+            None => return Ok(()),
+        }
+    }
+}
+
+async fn cp(client: &Client, src: &str, dst: &str, content_type: Option<&str>) -> Result<(), Error> {
+    match (remote(src), remote(dst)) {
+        // upload
+        (None, Some(uri)) => {
+            let path = Path::new(src);
+            let bucket = find_bucket(client, uri.bucket).await?;
+
+            // copying to a "directory" keeps the local file name
+            let file_name = match uri.path.is_empty() || uri.path.ends_with('/') {
+                true => {
+                    let name = path.file_name().ok_or("source has no file name")?.to_string_lossy();
+
+                    format!("{}{name}", uri.path)
+                }
+                false => uri.path.to_owned(),
+            };
+
+            let info = NewFileFromPath::builder()
+                .path(path)
+                .file_name(file_name.as_str())
+                .content_type(content_type.unwrap_or("b2/x-auto"))
+                .build();
+
+            client.upload_from_path(&info, Some(&bucket.bucket_id), None).await?;
+
+            Ok(())
+        }
+        // download
+        (Some(uri), None) => {
+            let bucket = find_bucket(client, uri.bucket).await?;
+            let file = find_versions(client, &bucket.bucket_id, uri.path, false).await?;
+
+            let mut path = PathBuf::from(dst);
+
+            if path.is_dir() {
+                path.push(uri.path.rsplit('/').next().unwrap_or(uri.path));
+            }
+
+            download(client, &file[0], tokio::fs::File::create(&path).await?).await
+        }
+        (Some(_), Some(_)) => Err("copying between buckets is not supported, use b2:// for one side only".into()),
+        (None, None) => Err("one of the arguments must be a b2:// URI".into()),
+    }
+}
+
+async fn download(client: &Client, file: &B2FileInfo, mut out: impl AsyncWriteExt + Unpin) -> Result<(), Error> {
+    let mut download = client.download_file(DownloadFileBy::FileId(&file.file_id), None, None).await?;
+
+    while let Some(chunk) = download.chunk().await? {
+        out.write_all(&chunk).await?;
+    }
+
+    out.flush().await?;
+
+    Ok(())
+}
+
+async fn rm(
+    client: &Client,
+    uri: &str,
+    versions: bool,
+    recursive: bool,
+    bypass_governance: bool,
+) -> Result<(), Error> {
+    let uri = require_remote(uri)?;
+    let bucket = find_bucket(client, uri.bucket).await?;
+
+    if recursive {
+        let options = DeleteOptions::builder().bypass_governance(bypass_governance).build();
+
+        let report = client.delete_prefix(Some(&bucket.bucket_id), uri.path, &options).await?;
+
+        println!(
+            "deleted {} file versions ({} bytes)",
+            report.files_deleted, report.bytes_deleted
+        );
+
+        for file in &report.legal_hold {
+            eprintln!("skipped (legal hold): {} {}", file.file_name, file.file_id);
+        }
+
+        for (file, e) in &report.failed {
+            eprintln!("failed: {} {}: {e}", file.file_name, file.file_id);
+        }
+
+        return match report.is_success() {
+            true => Ok(()),
+            false => Err("not every file version was deleted".into()),
+        };
+    }
+
+    for file in find_versions(client, &bucket.bucket_id, uri.path, versions).await? {
+        client.delete_file(&file.file_id, &file.file_name, bypass_governance).await?;
+    }
+
+    Ok(())
+}
+
+async fn buckets(client: &Client, command: BucketsCommand) -> Result<(), Error> {
+    match command {
+        BucketsCommand::Create { name, public } => {
+            let bucket =
+                client.create_bucket(&CreateBucket::builder().bucket_name(&name).public(public).build()).await?;
+
+            println!("{}", bucket.bucket_id);
+        }
+        BucketsCommand::Update { name, bucket_type } => {
+            let bucket = find_bucket(client, &name).await?;
+
+            let bucket_type = match bucket_type {
+                BucketType::AllPublic => B2BucketType::AllPublic,
+                BucketType::AllPrivate => B2BucketType::AllPrivate,
+            };
+
+            client
+                .update_bucket(
+                    &UpdateBucket::builder().bucket_id(&bucket.bucket_id).bucket_type(bucket_type).build(),
+                )
+                .await?;
+        }
+        BucketsCommand::List { json } => {
+            for bucket in client.list_buckets(&ListBuckets::builder().build()).await? {
+                match json {
+                    true => println!(
+                        "{}",
+                        json!({
+                            "bucketName": bucket.bucket_name,
+                            "bucketId": bucket.bucket_id,
+                            "bucketType": bucket.bucket_type,
+                            "bucketInfo": bucket.bucket_info,
+                            "revision": bucket.revision,
+                        })
+                    ),
+                    false => println!("{}\t{:?}\t{}", bucket.bucket_id, bucket.bucket_type, bucket.bucket_name),
+                }
+            }
+        }
+        BucketsCommand::Delete { name } => {
+            let bucket = find_bucket(client, &name).await?;
+
+            client.delete_bucket(&bucket.bucket_id).await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn keys(client: &Client, command: KeysCommand) -> Result<(), Error> {
+    match command {
+        KeysCommand::Create {
+            name,
+            capabilities,
+            bucket,
+            prefix,
+            duration,
+        } => {
+            let mut caps = B2Capability::empty();
+
+            for capability in &capabilities {
+                match B2Capability::ALL_CAPABILITIES_AND_NAMES.iter().find(|(_, name)| name == capability) {
+                    Some((c, _)) => caps |= *c,
+                    None => return Err(format!("unknown capability {capability:?}").into()),
+                }
+            }
+
+            let bucket_id = match bucket {
+                Some(ref bucket) => Some(find_bucket(client, bucket).await?.bucket_id),
+                None => None,
+            };
+
+            let key = client
+                .create_key(
+                    &CreateApplicationKey::builder()
+                        .key_name(&name)
+                        .capabilities(caps)
+                        .bucket_id(bucket_id.as_deref())
+                        .name_prefix(prefix.as_deref())
+                        .valid_duration_in_seconds(duration)
+                        .build(),
+                )
+                .await?;
+
+            println!(
+                "{}\t{}",
+                key.application_key_id,
+                key.application_key.as_deref().unwrap_or_default()
+            );
+        }
+        KeysCommand::List => {
+            let mut start_key_id = None::<SmolStr>;
+
+            loop {
+                let list = client.list_keys(start_key_id.as_deref(), Some(1000)).await?;
+
+                for key in &list.keys {
+                    println!(
+                        "{}\t{}\t{}",
+                        key.application_key_id,
+                        key.key_name,
+                        capability_names(*key.capabilities).join(",")
+                    );
+                }
+
+                match list.next_application_key_id {
+                    Some(next) => start_key_id = Some(next),
+                    None => break,
+                }
+            }
+        }
+        KeysCommand::Delete { key_id } => client.delete_key(&key_id).await?,
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remote() {
+        let parse = |uri| remote(uri).map(|uri| (uri.bucket, uri.path));
+
+        assert_eq!(parse("b2://bucket/a/b.txt"), Some(("bucket", "a/b.txt")));
+        assert_eq!(parse("b2://bucket/a/"), Some(("bucket", "a/")));
+        assert_eq!(parse("b2://bucket/"), Some(("bucket", "")));
+        assert_eq!(parse("b2://bucket"), Some(("bucket", "")));
+
+        assert_eq!(parse("bucket/a.txt"), None);
+        assert_eq!(parse("./b2://bucket"), None);
+        assert_eq!(parse("s3://bucket/a.txt"), None);
+
+        assert!(require_remote("a.txt").is_err());
+    }
+}
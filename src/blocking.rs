@@ -0,0 +1,219 @@
+//! A blocking client for the B2 API.
+//!
+//! The blocking [`Client`] wraps an async [`crate::Client`] and drives it on an internal
+//! current-thread tokio runtime, similar to `reqwest::blocking`.
+//!
+//! **NOTE**: The blocking client must not be used from within an async runtime, as it will panic.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use std::io::Read;
+//!
+//! let client = yab2::blocking::Client::authorize(ClientBuilder::new(&app_id, &app_key))?;
+//!
+//! let mut file = client.download_file(DownloadFileBy::FileName("test.txt"), None, None)?;
+//!
+//! let mut contents = String::new();
+//! file.read_to_string(&mut contents)?;
+//! ```
+
+use std::io::{self, Read};
+use std::sync::Arc;
+
+use bytes::{Buf, Bytes};
+use tokio::runtime::Runtime;
+
+use crate::models::{self, B2FileInfo};
+use crate::*;
+
+/// A blocking client for interacting with the B2 API.
+///
+/// Cheap to clone, with clones sharing the same runtime and authorization.
+#[derive(Clone)]
+pub struct Client {
+    inner: crate::Client,
+    rt: Arc<Runtime>,
+}
+
+/// A file being downloaded with the blocking [`Client`], implementing [`Read`].
+pub struct DownloadedFile {
+    /// Parsed header info from the response
+    pub info: models::B2FileHeaders,
+
+    body: transport::ResponseBody,
+    chunk: Bytes,
+    rt: Arc<Runtime>,
+}
+
+impl Client {
+    /// Builds and authorizes a blocking client for first use.
+    pub fn authorize(builder: ClientBuilder) -> Result<Client, B2Error> {
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+
+        Ok(Client {
+            inner: rt.block_on(builder.authorize())?,
+            rt: Arc::new(rt),
+        })
+    }
+
+    /// Returns the underlying async [`crate::Client`].
+    ///
+    /// Its futures must be driven by a runtime with IO and time enabled,
+    /// such as with [`Client::block_on`].
+    pub fn inner(&self) -> &crate::Client {
+        &self.inner
+    }
+
+    /// Runs a future to completion on the internal runtime, such as one using [`Client::inner`].
+    pub fn block_on<F: std::future::Future>(&self, f: F) -> F::Output {
+        self.rt.block_on(f)
+    }
+
+    /// Creates a new application key. See [`crate::Client::create_key`].
+    pub fn create_key(&self, key: &CreateApplicationKey<'_>) -> Result<models::B2ApplicationKey, B2Error> {
+        self.block_on(self.inner.create_key(key))
+    }
+
+    /// Lists application keys associated with an account. See [`crate::Client::list_keys`].
+    pub fn list_keys(
+        &self,
+        start_key_id: Option<&str>,
+        max_key_count: Option<usize>,
+    ) -> Result<models::B2ListedApplicationKey, B2Error> {
+        self.block_on(self.inner.list_keys(start_key_id, max_key_count))
+    }
+
+    /// Deletes an application key. See [`crate::Client::delete_key`].
+    pub fn delete_key(&self, key_id: &str) -> Result<(), B2Error> {
+        self.block_on(self.inner.delete_key(key_id))
+    }
+
+    /// Creates a new bucket. See [`crate::Client::create_bucket`].
+    pub fn create_bucket(&self, create: &CreateBucket<'_>) -> Result<models::B2Bucket, B2Error> {
+        self.block_on(self.inner.create_bucket(create))
+    }
+
+    /// Lists buckets associated with an account. See [`crate::Client::list_buckets`].
+    pub fn list_buckets(&self, query: &ListBuckets<'_>) -> Result<Vec<models::B2Bucket>, B2Error> {
+        self.block_on(self.inner.list_buckets(query))
+    }
+
+    /// Updates an existing bucket. See [`crate::Client::update_bucket`].
+    pub fn update_bucket(&self, update: &UpdateBucket<'_>) -> Result<models::B2Bucket, B2Error> {
+        self.block_on(self.inner.update_bucket(update))
+    }
+
+    /// Deletes an empty bucket. See [`crate::Client::delete_bucket`].
+    pub fn delete_bucket(&self, bucket_id: &str) -> Result<models::B2Bucket, B2Error> {
+        self.block_on(self.inner.delete_bucket(bucket_id))
+    }
+
+    /// Gets information about a file by its ID. See [`crate::Client::get_file_info`].
+    pub fn get_file_info(&self, file_id: &str) -> Result<B2FileInfo, B2Error> {
+        self.block_on(self.inner.get_file_info(file_id))
+    }
+
+    /// Downloads a file by its ID or name, returning a [`DownloadedFile`] that can be read
+    /// with [`Read`]. See [`crate::Client::download_file`].
+    pub fn download_file(
+        &self,
+        file: DownloadFileBy<'_>,
+        range: Option<headers::Range>,
+        encryption: Option<sse::ServerSideEncryptionCustomer>,
+    ) -> Result<DownloadedFile, B2Error> {
+        let file = self.block_on(self.inner.download_file(file, range, encryption))?;
+
+        Ok(DownloadedFile {
+            info: file.info,
+            body: file.resp.into_body(),
+            chunk: Bytes::new(),
+            rt: self.rt.clone(),
+        })
+    }
+
+    /// Lists the names of files in a bucket. See [`crate::Client::list_files`].
+    pub fn list_files(&self, args: &ListFiles<'_>) -> Result<models::B2FileInfoList, B2Error> {
+        self.block_on(self.inner.list_files(args))
+    }
+
+    /// Hides a file. See [`crate::Client::hide_file`].
+    pub fn hide_file(&self, bucket_id: Option<&str>, file_name: &str) -> Result<B2FileInfo, B2Error> {
+        self.block_on(self.inner.hide_file(bucket_id, file_name))
+    }
+
+    /// Deletes one version of a file. See [`crate::Client::delete_file`].
+    pub fn delete_file(&self, file_id: &str, file_name: &str, bypass_governance: bool) -> Result<(), B2Error> {
+        self.block_on(self.inner.delete_file(file_id, file_name, bypass_governance))
+    }
+
+    /// Copies a file on the server side. See [`crate::Client::copy_file`].
+    pub fn copy_file(&self, copy: &CopyFile<'_>) -> Result<B2FileInfo, B2Error> {
+        self.block_on(self.inner.copy_file(copy))
+    }
+
+    /// Acquires a new upload URL for the given bucket, then uploads `bytes` as a single file.
+    ///
+    /// If `bucket_id` is `None`, the client's default bucket will be used.
+    ///
+    /// See [`UploadUrl::upload_file_bytes`] for how the SHA1 hash is handled.
+    pub fn upload_file_bytes(
+        &self,
+        bucket_id: Option<&str>,
+        info: &NewFileInfo<'_>,
+        bytes: impl Into<Bytes>,
+    ) -> Result<B2FileInfo, B2Error> {
+        let bytes = bytes.into();
+
+        self.block_on(async {
+            let mut url = self.inner.get_upload_url(bucket_id).await?;
+
+            url.upload_file_bytes(info, bytes).await
+        })
+    }
+
+    /// Uploads the file at the given path, in parts if it is large. See [`crate::Client::upload_from_path`].
+    #[cfg(feature = "fs")]
+    pub fn upload_from_path(
+        &self,
+        info: &NewFileFromPath<'_>,
+        bucket_id: Option<&str>,
+    ) -> Result<B2FileInfo, B2Error> {
+        self.block_on(self.inner.upload_from_path(info, bucket_id, None))
+    }
+}
+
+impl DownloadedFile {
+    /// Reads the rest of the file into memory.
+    pub fn bytes(self) -> Result<Bytes, B2Error> {
+        use http_body_util::BodyExt;
+
+        let rest = self.rt.block_on(self.body.collect()).map_err(transport::body_error)?.to_bytes();
+
+        Ok(match self.chunk.is_empty() {
+            true => rest,
+            false => [self.chunk, rest].concat().into(),
+        })
+    }
+}
+
+impl Read for DownloadedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        while !self.chunk.has_remaining() {
+            match self.rt.block_on(transport::next_chunk(&mut self.body)).map_err(io::Error::other)? {
+                Some(chunk) => self.chunk = chunk,
+                None => return Ok(0),
+            }
+        }
+
+        let n = buf.len().min(self.chunk.len());
+
+        self.chunk.copy_to_slice(&mut buf[..n]);
+
+        Ok(n)
+    }
+}
@@ -0,0 +1,293 @@
+//! Operations on every file under a prefix.
+
+use futures_util::stream::{self, StreamExt};
+use smol_str::SmolStr;
+
+use crate::models::{B2FileAction, B2FileInfo};
+use crate::*;
+
+/// A single version of a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileVersion {
+    /// The name of the file.
+    pub file_name: SmolStr,
+
+    /// The ID of this version of the file.
+    pub file_id: SmolStr,
+}
+
+impl From<&B2FileInfo> for FileVersion {
+    fn from(file: &B2FileInfo) -> Self {
+        FileVersion {
+            file_name: file.file_name.clone(),
+            file_id: file.file_id.clone(),
+        }
+    }
+}
+
+/// Options for deleting files with [`Client::delete_prefix`].
+///
+/// See the documentation for [`DeleteOptions::builder`] for more information.
+#[derive(Default, Debug, Clone, Copy, typed_builder::TypedBuilder)]
+#[builder(doc)]
+pub struct DeleteOptions {
+    /// Delete file versions protected by Object Lock governance mode retention settings.
+    ///
+    /// Requires the `bypassGovernance` application key capability.
+    #[builder(default)]
+    pub bypass_governance: bool,
+
+    /// The maximum number of file versions to delete at once.
+    ///
+    /// If set to 0, the default of 4 deletions will be used.
+    #[builder(default)]
+    pub max_simultaneous_deletes: u8,
+}
+
+/// The results of [`Client::delete_prefix`].
+#[derive(Default, Debug)]
+pub struct DeleteReport {
+    /// The number of file versions deleted.
+    pub files_deleted: u64,
+
+    /// The total size of the file versions deleted, in bytes.
+    pub bytes_deleted: u64,
+
+    /// The number of hide markers deleted.
+    pub hide_markers_deleted: u64,
+
+    /// The number of unfinished large files cancelled.
+    pub large_files_cancelled: u64,
+
+    /// File versions that were skipped because they are under legal hold.
+    pub legal_hold: Vec<FileVersion>,
+
+    /// File versions that failed to delete, and the error that occurred.
+    pub failed: Vec<(FileVersion, B2Error)>,
+}
+
+impl DeleteReport {
+    /// Returns `true` if every file version was deleted.
+    pub fn is_success(&self) -> bool {
+        self.legal_hold.is_empty() && self.failed.is_empty()
+    }
+}
+
+/// The results of [`Client::hide_prefix`].
+#[derive(Default, Debug)]
+pub struct HideReport {
+    /// The number of files hidden.
+    pub hidden: u64,
+
+    /// Names of files that failed to be hidden, and the error that occurred.
+    pub failed: Vec<(SmolStr, B2Error)>,
+}
+
+impl HideReport {
+    /// Returns `true` if every file was hidden.
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+impl Client {
+    /// Deletes every version of every file whose name starts with `prefix`, including
+    /// hide markers and unfinished large files, such as for removing all of a user's data.
+    ///
+    /// If `bucket_id` is `None`, the client's default bucket will be used.
+    ///
+    /// File versions under legal hold are skipped, and listed in [`DeleteReport::legal_hold`]. If the
+    /// key cannot read legal holds, such versions are attempted and will appear in [`DeleteReport::failed`].
+    ///
+    /// An error is only returned if listing files fails, otherwise failures are collected
+    /// for each file version in the returned [`DeleteReport`].
+    ///
+    /// **NOTE**: An empty `prefix` deletes every file in the bucket.
+    pub async fn delete_prefix(
+        &self,
+        bucket_id: Option<&str>,
+        prefix: &str,
+        options: &DeleteOptions,
+    ) -> Result<DeleteReport, B2Error> {
+        let max_simultaneous_deletes = match options.max_simultaneous_deletes {
+            0 => 4,
+            n => n,
+        };
+
+        let mut report = DeleteReport::default();
+
+        let (mut start_file_name, mut start_file_id) = (None::<SmolStr>, None::<SmolStr>);
+
+        loop {
+            let list = self
+                .list_files(
+                    &ListFiles::builder()
+                        .all_versions(true)
+                        .bucket_id(bucket_id)
+                        .prefix((!prefix.is_empty()).then_some(prefix))
+                        .start_file_name(start_file_name.as_deref())
+                        .start_file_id(start_file_id.as_deref())
+                        .max_file_count(1000)
+                        .build(),
+                )
+                .await?;
+
+            let mut files = Vec::with_capacity(list.files.len());
+
+            for file in list.files {
+                match file.legal_hold.value.as_deref() {
+                    Some("on") => report.legal_hold.push(FileVersion::from(&file)),
+                    _ => files.push(file),
+                }
+            }
+
+            let deletes = stream::iter(files).map(|file| async move {
+                let res = self.delete_file(&file.file_id, &file.file_name, options.bypass_governance).await;
+
+                (file, res)
+            });
+
+            let mut deletes = deletes.buffer_unordered(max_simultaneous_deletes as usize);
+
+            while let Some((file, res)) = deletes.next().await {
+                if let Err(e) = res {
+                    report.failed.push((FileVersion::from(&file), e));
+                    continue;
+                }
+
+                match file.action {
+                    Some(B2FileAction::Hidden) => report.hide_markers_deleted += 1,
+                    Some(B2FileAction::Started) => report.large_files_cancelled += 1,
+                    _ => {
+                        report.files_deleted += 1;
+                        report.bytes_deleted += file.content_length;
+                    }
+                }
+            }
+
+            match list.next_file_name {
+                Some(next) => (start_file_name, start_file_id) = (Some(next), list.next_file_id),
+                None => return Ok(report),
+            }
+        }
+    }
+
+    /// Hides every file whose name starts with `prefix`, so that downloading by name will not find them,
+    /// but previous versions of the files are still stored.
+    ///
+    /// If `bucket_id` is `None`, the client's default bucket will be used.
+    ///
+    /// At most `max_simultaneous_hides` files are hidden at once, or 4 if set to 0.
+    /// The hidden files can be restored individually with [`Client::unhide_file`].
+    ///
+    /// An error is only returned if listing files fails, otherwise failures are collected
+    /// for each file in the returned [`HideReport`].
+    pub async fn hide_prefix(
+        &self,
+        bucket_id: Option<&str>,
+        prefix: &str,
+        max_simultaneous_hides: u8,
+    ) -> Result<HideReport, B2Error> {
+        let max_simultaneous_hides = match max_simultaneous_hides {
+            0 => 4,
+            n => n,
+        };
+
+        let mut report = HideReport::default();
+
+        let mut start_file_name = None::<SmolStr>;
+
+        loop {
+            let list = self
+                .list_files(
+                    &ListFiles::builder()
+                        .bucket_id(bucket_id)
+                        .prefix((!prefix.is_empty()).then_some(prefix))
+                        .start_file_name(start_file_name.as_deref())
+                        .max_file_count(1000)
+                        .build(),
+                )
+                .await?;
+
+            // unfinished large files and folders cannot be hidden
+            let files =
+                list.files.into_iter().filter(|file| matches!(file.action, None | Some(B2FileAction::Uploaded)));
+
+            let hides = stream::iter(files).map(|file| async move {
+                let res = self.hide_file(bucket_id, &file.file_name).await;
+
+                (file.file_name, res)
+            });
+
+            let mut hides = hides.buffer_unordered(max_simultaneous_hides as usize);
+
+            while let Some((file_name, res)) = hides.next().await {
+                match res {
+                    Ok(_) => report.hidden += 1,
+                    Err(e) => report.failed.push((file_name, e)),
+                }
+            }
+
+            match list.next_file_name {
+                Some(next) => start_file_name = Some(next),
+                None => return Ok(report),
+            }
+        }
+    }
+
+    /// Unhides a file hidden with [`Client::hide_file`] by deleting the hide marker,
+    /// so the most recent previous version becomes the current version again.
+    ///
+    /// If `bucket_id` is `None`, the client's default bucket will be used.
+    ///
+    /// Returns [`B2Error::NotHidden`] if the latest version of the file is not a hide marker,
+    /// including if the file does not exist.
+    pub async fn unhide_file(&self, bucket_id: Option<&str>, file_name: &str) -> Result<(), B2Error> {
+        // versions are listed from newest to oldest, so the first version of the file is the latest
+        let list = self
+            .list_files(
+                &ListFiles::builder()
+                    .all_versions(true)
+                    .bucket_id(bucket_id)
+                    .prefix(file_name)
+                    .start_file_name(file_name)
+                    .max_file_count(1)
+                    .build(),
+            )
+            .await?;
+
+        match list.files.first() {
+            Some(latest)
+                if latest.file_name == file_name && matches!(latest.action, Some(B2FileAction::Hidden)) =>
+            {
+                self.delete_file(&latest.file_id, &latest.file_name, false).await
+            }
+            _ => Err(B2Error::NotHidden(SmolStr::from(file_name))),
+        }
+    }
+
+    /// Restores an older version of a file by copying it on the server side to become the newest version,
+    /// in parts if it is too large for `b2_copy_file`, keeping its content type, file info, file retention
+    /// and legal hold settings.
+    ///
+    /// This also unhides the file if it is hidden. The newer versions are kept.
+    ///
+    /// `encryption` must be provided if the version is encrypted with SSE-C,
+    /// and the new version will be encrypted with the same key.
+    pub async fn restore_version(
+        &self,
+        file_id: &str,
+        encryption: Option<sse::ServerSideEncryptionCustomer>,
+    ) -> Result<B2FileInfo, B2Error> {
+        let file = self.get_file_info(file_id).await?;
+
+        self.copy_whole_file(
+            &file,
+            Some(&file.bucket_id),
+            &file.file_name,
+            encryption.clone(),
+            &encryption.into(),
+        )
+        .await
+    }
+}
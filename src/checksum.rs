@@ -0,0 +1,109 @@
+//! Streaming SHA1 checksums for upload bodies.
+
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt};
+use http_body_util::BodyDataStream;
+use sha1::{Digest, Sha1};
+
+pub(crate) type DynError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// Computes the SHA1 hash of the given bytes as a lowercase hex string.
+pub(crate) fn sha1_hex(bytes: &[u8]) -> String {
+    hex::encode(Sha1::digest(bytes))
+}
+
+/// Error yielded by a [`Sha1Stream`] at the end of the body if the content does not match the expected hash.
+///
+/// This aborts the request before B2 can accept the body.
+#[derive(Debug, thiserror::Error)]
+#[error("SHA1 mismatch")]
+pub(crate) struct Sha1StreamMismatch;
+
+/// The SHA1 hash computed by the most recent [`Sha1Stream`] to finish.
+///
+/// Shared between the stream and the caller, since the stream itself is consumed by `reqwest`.
+pub(crate) type Sha1Slot = Arc<Mutex<Option<String>>>;
+
+/// What to do with the SHA1 hash once the inner stream has finished.
+enum Trailer {
+    /// Compare the hash against the expected hex string, yielding an error on mismatch.
+    Verify(Arc<str>, Sha1Slot),
+
+    /// Append the hex digits of the hash to the end of the stream, for B2's `hex_digits_at_end` mode.
+    Append,
+}
+
+/// A stream of bytes that computes the SHA1 hash of its contents as they pass through.
+pub(crate) struct Sha1Stream<S> {
+    inner: S,
+    sha1: Option<Sha1>,
+    trailer: Trailer,
+}
+
+impl Sha1Stream<BodyDataStream<reqwest::Body>> {
+    fn wrap(body: impl Into<reqwest::Body>, trailer: Trailer) -> reqwest::Body {
+        reqwest::Body::wrap_stream(Sha1Stream {
+            inner: BodyDataStream::new(body.into()),
+            sha1: Some(Sha1::new()),
+            trailer,
+        })
+    }
+
+    /// Wraps a request body so that its SHA1 hash is compared against `expected` once fully streamed.
+    pub(crate) fn verify(body: impl Into<reqwest::Body>, expected: Arc<str>, slot: Sha1Slot) -> reqwest::Body {
+        Self::wrap(body, Trailer::Verify(expected, slot))
+    }
+
+    /// Wraps a request body so that the hex digits of its SHA1 hash are appended once fully streamed.
+    ///
+    /// The resulting body is 40 bytes longer than the original.
+    pub(crate) fn append(body: impl Into<reqwest::Body>) -> reqwest::Body {
+        Self::wrap(body, Trailer::Append)
+    }
+}
+
+impl<S, E> Stream for Sha1Stream<S>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: Into<DynError>,
+{
+    type Item = Result<Bytes, DynError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+
+        let Some(ref mut sha1) = this.sha1 else {
+            return Poll::Ready(None);
+        };
+
+        match this.inner.poll_next_unpin(cx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Some(Ok(chunk))) => {
+                sha1.update(&chunk);
+                Poll::Ready(Some(Ok(chunk)))
+            }
+            Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(e.into()))),
+            Poll::Ready(None) => {
+                let actual = hex::encode(this.sha1.take().expect("checked to be `Some` above").finalize());
+
+                Poll::Ready(match this.trailer {
+                    Trailer::Append => Some(Ok(Bytes::from(actual))),
+                    Trailer::Verify(ref expected, ref slot) => {
+                        let matches = expected.eq_ignore_ascii_case(&actual);
+
+                        *slot.lock().unwrap_or_else(|e| e.into_inner()) = Some(actual);
+
+                        match matches {
+                            true => None,
+                            false => Some(Err(Box::new(Sha1StreamMismatch))),
+                        }
+                    }
+                })
+            }
+        }
+    }
+}
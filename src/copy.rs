@@ -0,0 +1,131 @@
+//! Server-side copies of whole files, in parts if needed, shared by moves, restores and migrations.
+
+use std::num::NonZeroU32;
+
+use crate::models::{B2FileInfo, B2PartInfo};
+use crate::*;
+
+/// The largest file that can be copied with a single `b2_copy_file` call, 5GB.
+const MAX_COPY_FILE_SIZE: u64 = 5_000_000_000;
+
+/// The maximum number of parts in a large file.
+const MAX_PARTS: u64 = 10_000;
+
+/// Converts the retention settings of an existing file into settings for a new file, if readable.
+pub(crate) fn retention_of(file: &B2FileInfo) -> Option<FileRetention> {
+    let value = file.file_retention.value.as_ref()?;
+
+    Some(FileRetention::new(Some(value.mode)).retain_until_timestamp(Some(value.retain_until_timestamp)))
+}
+
+/// Gets the legal hold status of an existing file, if readable.
+pub(crate) fn legal_hold_of(file: &B2FileInfo) -> Option<bool> {
+    file.legal_hold.value.as_deref().map(|value| value == "on")
+}
+
+impl Client {
+    /// Copies a file on the server side, in parts if it is too large for `b2_copy_file`,
+    /// preserving its content type, file info, file retention and legal hold settings.
+    pub(crate) async fn copy_whole_file(
+        &self,
+        file: &B2FileInfo,
+        bucket_id: Option<&str>,
+        file_name: &str,
+        source_encryption: Option<sse::ServerSideEncryptionCustomer>,
+        encryption: &sse::ServerSideEncryption,
+    ) -> Result<B2FileInfo, B2Error> {
+        if file.content_length <= MAX_COPY_FILE_SIZE {
+            let copy = CopyFile::builder()
+                .source_file_id(&file.file_id)
+                .destination_bucket_id(bucket_id)
+                .file_name(file_name)
+                .retention(retention_of(file))
+                .legal_hold(legal_hold_of(file))
+                .source_encryption(source_encryption)
+                .encryption(encryption.clone())
+                .build();
+
+            return self.copy_file(&copy).await;
+        }
+
+        let recommended_part_size = self.state.read().await.account.api.storage.recommended_part_size;
+
+        let large = self.start_large_file_like(file, bucket_id, file_name, encryption).await?;
+
+        let mut parts = Vec::new();
+
+        for (part_number, range) in part_ranges(file.content_length, recommended_part_size) {
+            let copy = CopyPart::builder()
+                .source_file_id(&file.file_id)
+                .part_number(part_number)
+                .range(range)
+                .source_encryption(source_encryption.clone())
+                .encryption(encryption.clone())
+                .build();
+
+            match large.copy_part(&copy).await {
+                Ok(part) => parts.push(part),
+                Err(e) => return Err(cancel_after(large, e).await),
+            }
+        }
+
+        finish(large, &parts).await
+    }
+
+    /// Starts a large file with the same metadata as an existing file.
+    pub(crate) async fn start_large_file_like(
+        &self,
+        file: &B2FileInfo,
+        bucket_id: Option<&str>,
+        file_name: &str,
+        encryption: &sse::ServerSideEncryption,
+    ) -> Result<LargeFileUpload, B2Error> {
+        let info = NewLargeFileInfo {
+            file_name,
+            content_type: file.content_type.as_deref(),
+            encryption: encryption.clone(),
+            retention: retention_of(file),
+            legal_hold: legal_hold_of(file),
+            file_info: Some(&file.file_info),
+        };
+
+        self.start_large_file(bucket_id, &info).await
+    }
+}
+
+/// Splits a file into part numbers and inclusive byte ranges,
+/// using the recommended part size unless that would exceed the maximum number of parts.
+pub(crate) fn part_ranges(
+    content_length: u64,
+    recommended_part_size: u64,
+) -> Vec<(NonZeroU32, std::ops::RangeInclusive<u64>)> {
+    let part_size = recommended_part_size.max(content_length.div_ceil(MAX_PARTS)).max(1);
+
+    (0..content_length.div_ceil(part_size))
+        .map(|i| {
+            let start = i * part_size;
+            let end = (start + part_size).min(content_length) - 1;
+
+            // SAFETY: i + 1 is never zero, and there are at most MAX_PARTS parts
+            (unsafe { NonZeroU32::new_unchecked(i as u32 + 1) }, start..=end)
+        })
+        .collect()
+}
+
+/// Finishes a large file, cancelling it if that fails.
+pub(crate) async fn finish(large: LargeFileUpload, parts: &[B2PartInfo]) -> Result<B2FileInfo, B2Error> {
+    let client = large.client.clone();
+    let file_id = large.info().file_id.clone();
+
+    match large.finish(parts).await {
+        Ok(info) => Ok(info),
+        Err(e) => Err(cancel_after(LargeFileUpload::existing(&client, file_id), e).await),
+    }
+}
+
+/// Cancels a large file after an error, so the uploaded parts are not left behind.
+pub(crate) async fn cancel_after(large: LargeFileUpload, e: B2Error) -> B2Error {
+    _ = large.cancel().await;
+
+    e
+}
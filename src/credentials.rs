@@ -0,0 +1,236 @@
+//! Loading credentials for [`ClientBuilder`] from the environment or the official `b2` CLI,
+//! and rotating the application key of a [`Client`].
+
+use std::path::PathBuf;
+
+use smol_str::SmolStr;
+
+use crate::error::{CredentialsError, KeyRotationError};
+use crate::models::{self, B2StorageApi};
+use crate::{B2Error, Client, ClientBuilder, CreateApplicationKey};
+
+/// Environment variable holding the application key ID, as used by the official `b2` CLI.
+pub const KEY_ID_ENV: &str = "B2_APPLICATION_KEY_ID";
+
+/// Environment variable holding the application key, as used by the official `b2` CLI.
+pub const KEY_ENV: &str = "B2_APPLICATION_KEY";
+
+/// Environment variable overriding the path of the `b2` CLI account info database.
+pub const ACCOUNT_INFO_ENV: &str = "B2_ACCOUNT_INFO";
+
+fn env(name: &'static str) -> Result<String, CredentialsError> {
+    match std::env::var(name) {
+        Ok(value) if value.trim().is_empty() => Err(CredentialsError::InvalidEnv(name)),
+        Ok(value) => Ok(value.trim().to_owned()),
+        Err(std::env::VarError::NotPresent) => Err(CredentialsError::MissingEnv(name)),
+        Err(std::env::VarError::NotUnicode(_)) => Err(CredentialsError::InvalidEnv(name)),
+    }
+}
+
+/// Finds the `b2` CLI account info database, in the same order as the CLI itself:
+/// `$B2_ACCOUNT_INFO`, `~/.b2_account_info`, then `$XDG_CONFIG_HOME/b2/account_info`.
+pub fn account_info_path() -> Result<PathBuf, CredentialsError> {
+    if let Some(path) = std::env::var_os(ACCOUNT_INFO_ENV) {
+        return Ok(PathBuf::from(path));
+    }
+
+    let home = std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+        .ok_or(CredentialsError::NoHomeDir)?;
+
+    let legacy = home.join(".b2_account_info");
+
+    if legacy.exists() {
+        return Ok(legacy);
+    }
+
+    let config = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(config) if !config.is_empty() => PathBuf::from(config),
+        _ => home.join(".config"),
+    };
+
+    let xdg = config.join("b2").join("account_info");
+
+    match xdg.exists() {
+        true => Ok(xdg),
+        false => Err(CredentialsError::AccountInfoNotFound(legacy)),
+    }
+}
+
+impl ClientBuilder {
+    /// Creates a new client builder with the key ID and application key from the
+    /// `B2_APPLICATION_KEY_ID` and `B2_APPLICATION_KEY` environment variables.
+    pub fn from_env() -> Result<ClientBuilder, CredentialsError> {
+        Ok(ClientBuilder::new(&env(KEY_ID_ENV)?, &env(KEY_ENV)?))
+    }
+
+    /// Creates a new client builder with the credentials stored by the official `b2` CLI
+    /// after `b2 account authorize`, found with [`account_info_path`].
+    #[cfg(feature = "b2_account_info")]
+    pub fn from_account_info() -> Result<ClientBuilder, CredentialsError> {
+        ClientBuilder::from_account_info_path(account_info_path()?)
+    }
+
+    /// Creates a new client builder with the credentials stored in the given `b2` CLI account info database.
+    #[cfg(feature = "b2_account_info")]
+    pub fn from_account_info_path(path: impl AsRef<std::path::Path>) -> Result<ClientBuilder, CredentialsError> {
+        use rusqlite::{Connection, OpenFlags, OptionalExtension};
+
+        let path = path.as_ref();
+
+        if !path.exists() {
+            return Err(CredentialsError::AccountInfoNotFound(path.to_owned()));
+        }
+
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+
+        // older versions of the CLI only stored the account ID, which is valid for the master key
+        let query = match conn.prepare("SELECT application_key_id FROM account LIMIT 0") {
+            Ok(_) => "SELECT COALESCE(application_key_id, account_id), application_key FROM account LIMIT 1",
+            Err(_) => "SELECT account_id, application_key FROM account LIMIT 1",
+        };
+
+        let row = conn
+            .query_row(query, [], |row| {
+                Ok((row.get::<_, Option<String>>(0)?, row.get::<_, Option<String>>(1)?))
+            })
+            .optional()?;
+
+        match row {
+            Some((Some(key_id), Some(key))) if !key_id.is_empty() && !key.is_empty() => {
+                Ok(ClientBuilder::new(&key_id, &key))
+            }
+            Some(_) => Err(CredentialsError::MalformedAccountInfo(path.to_owned())),
+            None => Err(CredentialsError::NoAccount(path.to_owned())),
+        }
+    }
+}
+
+impl Client {
+    /// Switches the client to a new application key, such as when rotating keys.
+    ///
+    /// The new key is authorized and must have at least the capabilities of the current key, with the same
+    /// bucket and name prefix restrictions, otherwise [`B2Error::KeyRotation`] is returned and nothing changes.
+    ///
+    /// The new authorization is shared by every clone of the client, and any [`UploadUrl`](crate::UploadUrl)
+    /// or [`Pool`](crate::pool::Pool) created from it.
+    pub async fn rotate_credentials(&self, key_id: &str, app_key: &str) -> Result<(), B2Error> {
+        let _guard = self.reauth.lock().await;
+
+        let config = {
+            let state = self.state.read().await;
+
+            ClientBuilder {
+                key_id: SmolStr::from(key_id),
+                auth: models::create_auth_header(key_id, app_key),
+                ..state.config.clone()
+            }
+        };
+
+        let mut new_state = Client::do_auth(&self.transport, config).await?;
+
+        let mut state = self.state.write().await;
+
+        check_rotation(&state.account.api.storage, &new_state.account.api.storage)?;
+
+        new_state.generation = state.generation + 1;
+        *state = new_state;
+
+        Ok(())
+    }
+
+    /// Replaces the current application key with a newly created one with the same capabilities,
+    /// bucket and name prefix restrictions, then deletes the old key.
+    ///
+    /// Returns the new key, including its secret, which must be stored for future use.
+    ///
+    /// The current key requires the `writeKeys` and `deleteKeys` capabilities. If the new key cannot be
+    /// used, it is deleted again. If the old key cannot be deleted, [`KeyRotationError::DeleteOldKey`]
+    /// is returned with the new key, as the client will already be using it.
+    ///
+    /// The master application key cannot be rotated this way.
+    pub async fn rotate_key(
+        &self,
+        key_name: &str,
+        valid_duration_in_seconds: Option<u64>,
+    ) -> Result<models::B2ApplicationKey, B2Error> {
+        let (old_key_id, capabilities, bucket_id, name_prefix) = {
+            let state = self.state.read().await;
+
+            if state.config.key_id == state.account.account_id {
+                return Err(KeyRotationError::MasterKey.into());
+            }
+
+            let storage = &state.account.api.storage;
+
+            (
+                state.config.key_id.clone(),
+                storage.capabilities,
+                storage.bucket_id.clone(),
+                storage.name_prefix.clone(),
+            )
+        };
+
+        let new_key = self
+            .create_key(
+                &CreateApplicationKey::builder()
+                    .key_name(key_name)
+                    .capabilities(capabilities)
+                    .bucket_id(bucket_id.as_deref())
+                    .name_prefix(name_prefix.as_deref())
+                    .valid_duration_in_seconds(valid_duration_in_seconds)
+                    .build(),
+            )
+            .await?;
+
+        let Some(ref app_key) = new_key.application_key else {
+            _ = self.delete_key(&new_key.application_key_id).await;
+
+            return Err(KeyRotationError::MissingSecret(new_key.application_key_id).into());
+        };
+
+        if let Err(e) = self.rotate_credentials(&new_key.application_key_id, app_key).await {
+            _ = self.delete_key(&new_key.application_key_id).await;
+
+            return Err(e);
+        }
+
+        if let Err(error) = self.delete_key(&old_key_id).await {
+            return Err(KeyRotationError::DeleteOldKey {
+                new_key: Box::new(new_key),
+                old_key_id,
+                error,
+            }
+            .into());
+        }
+
+        Ok(new_key)
+    }
+}
+
+/// Checks that a new key can replace the current key, see [`Client::rotate_credentials`].
+fn check_rotation(current: &B2StorageApi, new: &B2StorageApi) -> Result<(), KeyRotationError> {
+    if !new.capabilities.contains(*current.capabilities) {
+        return Err(KeyRotationError::MissingCapabilities(
+            current.capabilities.difference(*new.capabilities),
+        ));
+    }
+
+    if current.bucket_id != new.bucket_id {
+        return Err(KeyRotationError::BucketMismatch {
+            expected: current.bucket_id.clone(),
+            found: new.bucket_id.clone(),
+        });
+    }
+
+    if current.name_prefix != new.name_prefix {
+        return Err(KeyRotationError::NamePrefixMismatch {
+            expected: current.name_prefix.as_deref().map(SmolStr::from),
+            found: new.name_prefix.as_deref().map(SmolStr::from),
+        });
+    }
+
+    Ok(())
+}
@@ -1,6 +1,10 @@
 //! Error Handling types for the B2 API.
 
-use crate::models::capabilities::B2Capability;
+use std::path::PathBuf;
+
+use smol_str::SmolStr;
+
+use crate::models::{self, capabilities::B2Capability, B2FileInfo, B2PartInfo};
 
 /// The B2 API returns errors in a JSON format. This struct represents that format.
 #[derive(Debug, Deserialize)]
@@ -65,6 +69,240 @@ pub enum B2Error {
 
     #[error("Invalid/Mismatched Prefix")]
     InvalidPrefix,
+
+    /// A custom file info key cannot be sent as an `X-Bz-Info-*` header.
+    #[error("Invalid File Info Key: {0}")]
+    InvalidFileInfoKey(SmolStr),
+
+    /// More custom file info entries were given than B2 allows.
+    #[error("Too Many File Info Entries: {0}, at most 10 are allowed")]
+    TooManyFileInfo(usize),
+
+    /// A large file upload failed part-way through without being cancelled, and can be resumed.
+    #[error("Incomplete Large File: {0}")]
+    IncompleteLargeFile(Box<IncompleteLargeFile>),
+
+    /// A glob pattern could not be parsed.
+    #[cfg(feature = "fs")]
+    #[error("Invalid Glob Pattern: {0}")]
+    InvalidGlob(#[from] globset::Error),
+
+    /// The operation was cancelled.
+    #[error("Cancelled")]
+    Cancelled,
+
+    /// The SHA1 hash of the content was not provided, and could not be computed.
+    #[error("Missing SHA1 Hash")]
+    MissingSha1,
+
+    /// The SHA1 hash of the uploaded content did not match the expected hash.
+    #[error("SHA1 Mismatch: expected {expected}, computed {actual}")]
+    Sha1Mismatch {
+        /// The SHA1 hash that was provided.
+        expected: String,
+        /// The SHA1 hash computed from the content.
+        actual: String,
+    },
+
+    /// The length of the content did not match the expected length.
+    #[error("Content Length Mismatch: expected {expected}, found {actual}")]
+    ContentLengthMismatch {
+        /// The expected length, in bytes.
+        expected: u64,
+        /// The actual length, in bytes.
+        actual: u64,
+    },
+
+    /// Moving a file failed part-way through, see [`MoveFileError`] for what state the files were left in.
+    #[error("Move File Error: {0}")]
+    MoveFile(Box<MoveFileError>),
+
+    /// Credentials could not be loaded.
+    #[error("Credentials Error: {0}")]
+    Credentials(#[from] CredentialsError),
+
+    /// No account with this name was added to the [`ClientRegistry`](crate::registry::ClientRegistry).
+    #[error("Unknown Account: {0}")]
+    UnknownAccount(SmolStr),
+
+    /// The bucket is not routed to any account in the [`ClientRegistry`](crate::registry::ClientRegistry).
+    #[error("Unknown Bucket: {0}")]
+    UnknownBucket(SmolStr),
+
+    /// Rotating the application key of a client failed.
+    #[error("Key Rotation Error: {0}")]
+    KeyRotation(Box<KeyRotationError>),
+
+    /// An [`HttpTransport`](crate::transport::HttpTransport) failed to send a request or receive a response.
+    #[error("Transport Error: {0}")]
+    Transport(#[source] Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    /// A request could not be replayed, see [`ReplayTransport`](crate::replay::ReplayTransport).
+    #[error("Replay Error: {0}")]
+    Replay(#[from] ReplayError),
+
+    /// The latest version of the file is not a hide marker,
+    /// see [`Client::unhide_file`](crate::Client::unhide_file).
+    #[error("Not Hidden: {0}")]
+    NotHidden(SmolStr),
+
+    /// The authorization token cannot be sent as a header value.
+    #[error("Invalid Authorization Token")]
+    InvalidAuthToken,
+
+    /// An upload made no progress within the [stall timeout](crate::ClientBuilder::stall_timeout).
+    #[error("Stalled: no progress for {0:?}")]
+    Stalled(std::time::Duration),
+}
+
+/// Errors from [`Client::rotate_credentials`](crate::Client::rotate_credentials)
+/// and [`Client::rotate_key`](crate::Client::rotate_key).
+#[derive(Debug, thiserror::Error)]
+pub enum KeyRotationError {
+    /// The new key is missing capabilities of the current key.
+    #[error("Missing Capabilities: {0:?}")]
+    MissingCapabilities(B2Capability),
+
+    /// The new key is restricted to a different bucket than the current key.
+    #[error("Bucket Mismatch: expected {expected:?}, found {found:?}")]
+    BucketMismatch {
+        /// The bucket the current key is restricted to.
+        expected: Option<SmolStr>,
+        /// The bucket the new key is restricted to.
+        found: Option<SmolStr>,
+    },
+
+    /// The new key is restricted to a different name prefix than the current key.
+    #[error("Name Prefix Mismatch: expected {expected:?}, found {found:?}")]
+    NamePrefixMismatch {
+        /// The name prefix the current key is restricted to.
+        expected: Option<SmolStr>,
+        /// The name prefix the new key is restricted to.
+        found: Option<SmolStr>,
+    },
+
+    /// The new key was created without returning its secret, so could not be used, and was deleted again.
+    #[error("Missing Secret for new key {0}")]
+    MissingSecret(SmolStr),
+
+    /// The master application key cannot be replaced with [`Client::rotate_key`](crate::Client::rotate_key).
+    #[error("Cannot Rotate Master Key")]
+    MasterKey,
+
+    /// The client switched to the new key, but the old key could not be deleted.
+    ///
+    /// The new key is in use and must be stored, and the old key should be deleted manually.
+    #[error("Failed to delete old key {old_key_id}: {error}")]
+    DeleteOldKey {
+        /// The new key, including its secret, which is now in use by the client.
+        new_key: Box<models::B2ApplicationKey>,
+        /// The ID of the old key that could not be deleted.
+        old_key_id: SmolStr,
+        /// The error that occurred deleting the old key.
+        #[source]
+        error: B2Error,
+    },
+}
+
+impl From<KeyRotationError> for B2Error {
+    fn from(e: KeyRotationError) -> Self {
+        B2Error::KeyRotation(Box::new(e))
+    }
+}
+
+/// Errors loading credentials, such as with [`ClientBuilder::from_env`](crate::ClientBuilder::from_env).
+#[derive(Debug, thiserror::Error)]
+pub enum CredentialsError {
+    /// An environment variable is not set.
+    #[error("Missing Environment Variable: {0}")]
+    MissingEnv(&'static str),
+
+    /// An environment variable is empty or not valid unicode.
+    #[error("Invalid Environment Variable: {0}")]
+    InvalidEnv(&'static str),
+
+    /// The home directory could not be found, so neither could the account info.
+    #[error("No Home Directory")]
+    NoHomeDir,
+
+    /// The account info database does not exist.
+    #[error("Account Info Not Found: {}", .0.display())]
+    AccountInfoNotFound(PathBuf),
+
+    /// The account info database has no account, so `b2 account authorize` has not been run.
+    #[error("No Account In Account Info: {}", .0.display())]
+    NoAccount(PathBuf),
+
+    /// The account in the account info database is missing its key ID or application key.
+    #[error("Malformed Account Info: {}", .0.display())]
+    MalformedAccountInfo(PathBuf),
+
+    /// The account info database could not be read.
+    #[cfg(feature = "b2_account_info")]
+    #[error("Account Info Error: {0}")]
+    AccountInfo(#[from] rusqlite::Error),
+}
+
+/// The step of [`Client::move_file`](crate::Client::move_file) that failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveFileStep {
+    /// Copying the file to its new name failed, or the source has no known SHA1 hash to verify the copy with.
+    /// Nothing was changed.
+    Copy,
+
+    /// The copy did not match the source file, and was deleted. If deleting it failed too,
+    /// it is given in [`MoveFileError::copy`] and should not be trusted.
+    Verify,
+
+    /// Deleting the source file failed. Both files exist with the same content.
+    DeleteSource,
+}
+
+/// A file move that failed part-way through.
+///
+/// Returned by [`Client::move_file`](crate::Client::move_file) in [`B2Error::MoveFile`].
+#[derive(Debug, thiserror::Error)]
+#[error("{file_name} -> {new_name} failed at {step:?}: {error}")]
+pub struct MoveFileError {
+    /// The step that failed.
+    pub step: MoveFileStep,
+
+    /// The name of the source file.
+    pub file_name: SmolStr,
+
+    /// The ID of the source file version.
+    pub file_id: SmolStr,
+
+    /// The new name of the file.
+    pub new_name: SmolStr,
+
+    /// The copy of the file, if it was created and still exists.
+    pub copy: Option<B2FileInfo>,
+
+    /// The error that stopped the move.
+    #[source]
+    pub error: B2Error,
+}
+
+/// A large file upload that failed part-way through, but was not cancelled.
+///
+/// Returned by [`Client::upload_from_path`](crate::Client::upload_from_path) in [`B2Error::IncompleteLargeFile`],
+/// and can be passed back to it to resume the upload, only uploading the missing parts.
+#[derive(Debug, thiserror::Error)]
+#[error("{file_id} ({} parts uploaded): {error}", parts.len())]
+pub struct IncompleteLargeFile {
+    /// The ID of the unfinished large file.
+    pub file_id: SmolStr,
+
+    /// The size of each part, except the last.
+    pub part_size: u64,
+
+    /// The parts that were uploaded successfully.
+    pub parts: Vec<B2PartInfo>,
+
+    /// The error that stopped the upload.
+    #[source]
+    pub error: B2Error,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -87,3 +325,27 @@ pub enum B2FileHeaderError {
     #[error("Invalid Retention Mode")]
     InvalidRetentionMode,
 }
+
+/// Errors from replaying recorded interactions with a [`ReplayTransport`](crate::replay::ReplayTransport).
+#[derive(Debug, thiserror::Error)]
+pub enum ReplayError {
+    /// A request was made that matches none of the remaining recorded interactions.
+    #[error("Unexpected Request: {method} {url}, next recorded request is {}", next.as_deref().unwrap_or("none"))]
+    UnexpectedRequest {
+        /// The HTTP method of the request.
+        method: String,
+        /// The URL of the request.
+        url: String,
+        /// The method and URL of the next recorded request, if any remain.
+        next: Option<String>,
+    },
+
+    /// Recorded interactions were never replayed.
+    #[error("Unused Interactions: {count} recorded requests were never made, next is {next}")]
+    Unused {
+        /// How many interactions were not replayed.
+        count: usize,
+        /// The method and URL of the next recorded request.
+        next: String,
+    },
+}
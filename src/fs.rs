@@ -1,85 +1,233 @@
+use std::collections::HashMap;
 use std::error::Error;
 use std::num::NonZeroU32;
 use std::path::PathBuf;
-use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use std::{io::SeekFrom, path::Path, sync::Arc};
 
 use tokio::fs::File;
 use tokio::io::{AsyncReadExt, AsyncSeekExt};
-use tokio::sync::{Mutex, OwnedMutexGuard};
+use tokio::sync::{watch, Mutex, OwnedMutexGuard};
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
 
-use futures_util::stream::{self, StreamExt, TryStreamExt};
+use futures_util::stream;
 use futures_util::FutureExt;
 
 use bytes::{Bytes, BytesMut};
 use reqwest::Body;
-
-use sha1::{Digest, Sha1};
+use smol_str::SmolStr;
 
 type DynError = Box<dyn Error + Send + Sync + 'static>;
 
+use crate::error::IncompleteLargeFile;
 use crate::*;
 
+mod dir;
+
+pub use dir::{UploadDirOptions, UploadDirOptionsBuilder, UploadDirReport};
+
+pub(crate) use dir::{walk_dir, LocalFile, PathFilter};
+
+/// File info key for the original modification time of a file, in milliseconds since the Unix epoch.
+pub(crate) const SRC_LAST_MODIFIED_MILLIS: &str = "src_last_modified_millis";
+
+/// Gets the modification time of a file in milliseconds since the Unix epoch, if available.
+pub(crate) fn modified_millis(metadata: &std::fs::Metadata) -> Option<u64> {
+    let modified = metadata.modified().ok()?;
+
+    Some(modified.duration_since(std::time::UNIX_EPOCH).ok()?.as_millis() as u64)
+}
+
 #[cfg(not(feature = "large_buffers"))]
 const DEFAULT_BUF_SIZE: usize = 8 * 1024;
 
 #[cfg(feature = "large_buffers")]
 const DEFAULT_BUF_SIZE: usize = 64 * 1024;
 
-async fn hash_chunk(file: &mut File, start: u64, end: u64) -> Result<String, B2Error> {
-    file.seek(SeekFrom::Start(start)).await?;
+/// A snapshot of the progress of an upload from [`Client::upload_from_path`].
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UploadProgress {
+    /// The total length of the file in bytes.
+    pub total_bytes: u64,
+
+    /// The number of bytes read from disk and hashed so far, including any that had to be re-sent.
+    pub bytes_hashed: u64,
+
+    /// The number of bytes in parts that have been fully uploaded and acknowledged by B2.
+    pub bytes_sent: u64,
+
+    /// The number of parts that have been fully uploaded.
+    pub parts_completed: u32,
+
+    /// The total number of parts to upload. Small files are uploaded as a single part.
+    pub num_parts: u32,
+
+    /// The number of times a part had to be re-sent, such as after the upload URL expired.
+    pub retries: u32,
+
+    /// The part whose progress caused this update, if any.
+    pub part: Option<PartProgress>,
+}
+
+/// The progress of a single part of an upload, see [`UploadProgress::part`].
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartProgress {
+    /// The part number, starting from 1. Small files are uploaded as part 1.
+    pub part_number: u32,
 
-    let mut sha1 = Sha1::new();
+    /// The length of the part in bytes.
+    pub content_length: u64,
 
-    let chunk_length = end - start;
+    /// The number of bytes of the part read from disk and hashed so far, restarting from 0 if the part is re-sent.
+    pub bytes_hashed: u64,
 
-    let mut read = 0;
-    let mut buf = [0; DEFAULT_BUF_SIZE];
+    /// Whether the part has been fully uploaded and acknowledged by B2.
+    pub completed: bool,
+}
+
+/// Receives [`UploadProgress`] updates from [`Client::upload_from_path`].
+///
+/// Implemented for closures taking an `UploadProgress` and for [`tokio::sync::watch::Sender`].
+///
+/// Updates are sent frequently and from multiple tasks, so implementations should be cheap.
+pub trait UploadProgressSink: Send + Sync {
+    /// Reports the current progress of the upload.
+    fn report(&self, progress: UploadProgress);
+}
+
+impl<F> UploadProgressSink for F
+where
+    F: Fn(UploadProgress) + Send + Sync,
+{
+    #[inline]
+    fn report(&self, progress: UploadProgress) {
+        self(progress)
+    }
+}
+
+impl UploadProgressSink for watch::Sender<UploadProgress> {
+    #[inline]
+    fn report(&self, progress: UploadProgress) {
+        self.send_replace(progress);
+    }
+}
 
-    while read < chunk_length {
-        let remaining = (chunk_length - read).min(DEFAULT_BUF_SIZE as u64) as usize;
+/// Shared progress counters for a single upload.
+struct Progress {
+    sink: Option<Arc<dyn UploadProgressSink>>,
+    total_bytes: u64,
+    num_parts: u32,
+    bytes_hashed: AtomicU64,
+    bytes_sent: AtomicU64,
+    parts_completed: AtomicU32,
+    retries: AtomicU32,
+}
 
-        let mut write_buf = &mut buf[..remaining];
-        while !write_buf.is_empty() {
-            file.read_buf(&mut write_buf).await?;
+impl Progress {
+    fn new(sink: Option<Arc<dyn UploadProgressSink>>, total_bytes: u64, num_parts: u32) -> Self {
+        Progress {
+            sink,
+            total_bytes,
+            num_parts,
+            bytes_hashed: AtomicU64::new(0),
+            bytes_sent: AtomicU64::new(0),
+            parts_completed: AtomicU32::new(0),
+            retries: AtomicU32::new(0),
         }
+    }
 
-        sha1.update(&buf[..remaining]);
-        read += remaining as u64;
+    fn report(&self, part: Option<PartProgress>) {
+        if let Some(ref sink) = self.sink {
+            sink.report(UploadProgress {
+                total_bytes: self.total_bytes,
+                bytes_hashed: self.bytes_hashed.load(Ordering::Relaxed),
+                bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+                parts_completed: self.parts_completed.load(Ordering::Relaxed),
+                num_parts: self.num_parts,
+                retries: self.retries.load(Ordering::Relaxed),
+                part,
+            });
+        }
+    }
+
+    fn part_hashed(&self, part: PartProgress, bytes: u64) {
+        self.bytes_hashed.fetch_add(bytes, Ordering::Relaxed);
+        self.report(Some(part));
+    }
+
+    fn part_completed(&self, part_number: u32, bytes: u64) {
+        self.parts_completed.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(bytes, Ordering::Relaxed);
+
+        self.report(Some(PartProgress {
+            part_number,
+            content_length: bytes,
+            bytes_hashed: bytes,
+            completed: true,
+        }));
     }
+}
 
-    Ok(hex::encode(sha1.finalize()))
+/// Resolves when the token is cancelled, or never if there is no token.
+async fn cancelled(token: Option<&CancellationToken>) {
+    match token {
+        Some(token) => token.cancelled().await,
+        None => std::future::pending().await,
+    }
 }
 
-#[derive(Debug, Clone)]
 struct FileChunk {
     file: Arc<Mutex<File>>,
+    part_number: u32,
     start: u64,
     end: u64,
+    progress: Arc<Progress>,
+    attempts: AtomicU32,
 }
 
 impl FileChunk {
     #[inline]
-    const fn new(file: Arc<Mutex<File>>, start: u64, end: u64) -> Self {
-        Self { file, start, end }
+    const fn new(file: Arc<Mutex<File>>, part_number: u32, start: u64, end: u64, progress: Arc<Progress>) -> Self {
+        Self {
+            file,
+            part_number,
+            start,
+            end,
+            progress,
+            attempts: AtomicU32::new(0),
+        }
     }
 }
 
 impl MakeBody<Body> for FileChunk {
     fn make(&self) -> Body {
-        let (start, end) = (self.start, self.end);
+        let (part_number, start, end) = (self.part_number, self.start, self.end);
 
         let num_chunks = (end - start).div_ceil(DEFAULT_BUF_SIZE as u64) as usize;
 
+        // any body after the first is a retry
+        if self.attempts.fetch_add(1, Ordering::Relaxed) > 0 {
+            self.progress.retries.fetch_add(1, Ordering::Relaxed);
+            self.progress.report(None);
+        }
+
         // Pretty much guaranteed to be able to lock the file, so just do it.
         let file = Mutex::try_lock_owned(self.file.clone()).expect("Unable to lock file");
 
         struct State {
             file: OwnedMutexGuard<File>,
+            progress: Arc<Progress>,
             chunk: u64,
         }
 
-        Body::wrap_stream(stream::unfold(State { file, chunk: 0 }, move |mut state| async move {
+        let state = State {
+            file,
+            progress: self.progress.clone(),
+            chunk: 0,
+        };
+
+        Body::wrap_stream(stream::unfold(state, move |mut state| async move {
             if state.chunk >= num_chunks as u64 {
                 return None;
             }
@@ -108,6 +256,15 @@ impl MakeBody<Body> for FileChunk {
 
                 state.chunk += 1;
 
+                let part = PartProgress {
+                    part_number,
+                    content_length: end - start,
+                    bytes_hashed: chunk_end - start,
+                    completed: false,
+                };
+
+                state.progress.part_hashed(part, remaining as u64);
+
                 Ok::<Bytes, DynError>(buf.freeze())
             };
 
@@ -125,7 +282,7 @@ impl MakeBody<Body> for FileChunk {
 /// See the documentation for [`NewFileFromPath::builder`] for more information.
 ///
 /// Used in [`Client::upload_from_path`].
-#[derive(Debug, typed_builder::TypedBuilder)]
+#[derive(typed_builder::TypedBuilder)]
 #[builder(doc)]
 pub struct NewFileFromPath<'a> {
     /// The filesystem path of the file to upload.
@@ -162,6 +319,64 @@ pub struct NewFileFromPath<'a> {
     /// Whether to apply a legal hold to the file.
     #[builder(default)]
     pub legal_hold: Option<bool>,
+
+    /// Custom file info to store with the file, up to 10 entries.
+    ///
+    /// Unless already provided, `src_last_modified_millis` will be set from
+    /// the modification time of the file on the local file system, which counts towards the limit,
+    /// so only 9 other entries can be given. [`B2Error::TooManyFileInfo`] is returned otherwise.
+    #[builder(default, setter(into))]
+    pub file_info: Option<&'a HashMap<SmolStr, SmolStr>>,
+
+    /// Receives progress updates while the file is uploaded.
+    ///
+    /// Can be a closure taking an [`UploadProgress`] or a [`tokio::sync::watch::Sender`].
+    #[builder(default, setter(transform = |sink: impl UploadProgressSink + 'static| {
+        Some(Arc::new(sink) as Arc<dyn UploadProgressSink>)
+    }))]
+    pub progress: Option<Arc<dyn UploadProgressSink>>,
+
+    /// Cancels the upload when triggered.
+    ///
+    /// Any parts being uploaded are aborted and the large file is cancelled,
+    /// so no unfinished large file is left behind, unless [`resumable`](NewFileFromPath::resumable) is set,
+    /// in which case the upload is only paused and can be resumed later.
+    #[builder(default, setter(into))]
+    pub cancel: Option<CancellationToken>,
+
+    /// If `true`, a large file upload that fails or is [cancelled](NewFileFromPath::cancel) part-way through
+    /// is not cancelled on B2, and [`B2Error::IncompleteLargeFile`] is returned instead, wrapping the error
+    /// or [`B2Error::Cancelled`], which can be passed to [`resume`](NewFileFromPath::resume) to continue the upload later.
+    ///
+    /// Otherwise, any parts still being uploaded are aborted and the large file is cancelled.
+    #[builder(default)]
+    pub resumable: bool,
+
+    /// Resumes a large file upload that previously failed with [`B2Error::IncompleteLargeFile`],
+    /// only uploading the missing parts. Recorded parts beyond the end of the file are ignored.
+    ///
+    /// The file at [`path`](NewFileFromPath::path) must not have changed since.
+    #[builder(default, setter(into))]
+    pub resume: Option<&'a IncompleteLargeFile>,
+}
+
+impl std::fmt::Debug for NewFileFromPath<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NewFileFromPath")
+            .field("path", &self.path)
+            .field("file_name", &self.file_name)
+            .field("content_type", &self.content_type)
+            .field("max_simultaneous_uploads", &self.max_simultaneous_uploads)
+            .field("encryption", &self.encryption)
+            .field("retention", &self.retention)
+            .field("legal_hold", &self.legal_hold)
+            .field("file_info", &self.file_info)
+            .field("progress", &self.progress.is_some())
+            .field("cancel", &self.cancel)
+            .field("resumable", &self.resumable)
+            .field("resume", &self.resume)
+            .finish()
+    }
 }
 
 impl Client {
@@ -169,13 +384,23 @@ impl Client {
     ///
     /// If the file is larger than the recommended part size, it will be uploaded in parts as a large file.
     /// Otherwise it will be uploaded as a single file, making use of the existing URL if provided.
+    ///
+    /// The SHA1 hash of each file or part is computed while it is being uploaded and sent at the end
+    /// of the body using B2's `hex_digits_at_end` mode, so the file is only read from disk once.
+    ///
+    /// Progress can be reported with [`NewFileFromPath::progress`], and the upload can be cancelled
+    /// with [`NewFileFromPath::cancel`], in which case [`B2Error::Cancelled`] is returned.
+    ///
+    /// If uploading a large file fails or is cancelled, any parts still being uploaded are aborted and the large file
+    /// is cancelled, unless [`NewFileFromPath::resumable`] is set. If the large file could not be cancelled,
+    /// or finishing it failed, [`B2Error::IncompleteLargeFile`] is returned so it can be cleaned up or resumed.
     pub async fn upload_from_path(
         &self,
         info: &NewFileFromPath<'_>,
         bucket_id: Option<&str>,
         existing_url: Option<&mut UploadUrl>,
     ) -> Result<models::B2FileInfo, B2Error> {
-        let mut file = tokio::fs::File::open(info.path).await?;
+        let file = tokio::fs::File::open(info.path).await?;
 
         let (metadata, recommended_part_size) = tokio::join!(file.metadata(), async {
             self.state.read().await.account.api.storage.recommended_part_size
@@ -189,8 +414,30 @@ impl Client {
             None => info.path.file_name().ok_or(B2Error::MissingFileName)?.to_string_lossy(),
         };
 
+        let mut file_info = info.file_info.cloned().unwrap_or_default();
+
+        if !file_info.contains_key(SRC_LAST_MODIFIED_MILLIS) {
+            if let Some(modified) = modified_millis(&metadata) {
+                file_info.insert(
+                    SmolStr::new_static(SRC_LAST_MODIFIED_MILLIS),
+                    modified.to_string().into(),
+                );
+            }
+        }
+
+        // fail before reading the file, including when there is no room left for the modification time
+        if file_info.len() > types::MAX_FILE_INFO {
+            return Err(B2Error::TooManyFileInfo(file_info.len()));
+        }
+
+        let file_info = &file_info;
+
         // small file, upload as a single file
-        if length <= recommended_part_size {
+        if length <= recommended_part_size && info.resume.is_none() {
+            let progress = Arc::new(Progress::new(info.progress.clone(), length, 1));
+
+            progress.report(None);
+
             // Box the future to avoid bloating the stack too much, especially with large DEFAULT_BUF_SIZE
             let do_upload = Box::pin(async move {
                 let mut new_url; // store the new URL if we have to get one
@@ -202,28 +449,38 @@ impl Client {
                     }
                 };
 
-                let content_length = metadata.len();
-                let content_sha1 = hash_chunk(&mut file, 0, length).await?;
-
                 let file = Arc::new(Mutex::new(file));
 
                 let whole_info = NewFileInfo {
                     file_name: &file_name,
                     content_type: info.content_type,
-                    content_length,
-                    content_sha1: &content_sha1,
+                    content_length: length,
+                    content_sha1: ContentSha1::Compute,
                     encryption: info.encryption.clone(),
                     retention: info.retention.clone(),
                     legal_hold: info.legal_hold,
+                    file_info: Some(file_info),
                 };
 
-                url.upload_file(&whole_info, FileChunk::new(file, 0, length)).await
+                let res = url.upload_file(&whole_info, FileChunk::new(file, 1, 0, length, progress.clone())).await;
+
+                if res.is_ok() {
+                    progress.part_completed(1, length);
+                }
+
+                res
             });
 
-            return do_upload.await;
+            return tokio::select! {
+                biased;
+                _ = cancelled(info.cancel.as_ref()) => Err(B2Error::Cancelled),
+                res = do_upload => res,
+            };
         }
 
-        let num_parts = length.div_ceil(recommended_part_size);
+        // resumed uploads must use the same part size as before
+        let part_size = info.resume.map_or(recommended_part_size, |resume| resume.part_size);
+        let num_parts = length.div_ceil(part_size);
 
         let max_simultaneous_uploads = (num_parts as usize).min(match info.max_simultaneous_uploads {
             0 => match std::thread::available_parallelism() {
@@ -233,103 +490,191 @@ impl Client {
             _ => info.max_simultaneous_uploads as usize,
         });
 
-        let large = self
-            .start_large_file(
-                bucket_id,
-                &NewLargeFileInfo {
-                    file_name: &file_name,
-                    content_type: info.content_type,
-                    encryption: info.encryption.clone(),
-                    retention: info.retention.clone(),
-                    legal_hold: info.legal_hold,
-                },
-            )
-            .boxed()
-            .await?;
+        let (large, mut parts) = match info.resume {
+            Some(resume) => {
+                let mut parts = resume.parts.clone();
+
+                // parts past the end of the file would make finishing it fail
+                parts.retain(|part| (1..=num_parts).contains(&part.part_number));
+
+                (LargeFileUpload::existing(self, resume.file_id.clone()), parts)
+            }
+            None => {
+                let large = self
+                    .start_large_file(
+                        bucket_id,
+                        &NewLargeFileInfo {
+                            file_name: &file_name,
+                            content_type: info.content_type,
+                            encryption: info.encryption.clone(),
+                            retention: info.retention.clone(),
+                            legal_hold: info.legal_hold,
+                            file_info: Some(file_info),
+                        },
+                    )
+                    .boxed()
+                    .await?;
+
+                (large, Vec::with_capacity(num_parts as usize))
+            }
+        };
 
         struct SharedInfo {
             large: LargeFileUpload,
             part: AtomicU32,
+            completed: Vec<bool>,
             path: PathBuf,
             encryption: sse::ServerSideEncryption,
+            progress: Arc<Progress>,
         }
 
-        let info = Arc::new(SharedInfo {
+        let mut completed = vec![false; num_parts as usize];
+        let progress = Progress::new(info.progress.clone(), length, num_parts as u32);
+
+        // skip any parts already uploaded
+        for part in &parts {
+            if let Some(done) = part.part_number.checked_sub(1).and_then(|idx| completed.get_mut(idx as usize)) {
+                *done = true;
+
+                progress.parts_completed.fetch_add(1, Ordering::Relaxed);
+                progress.bytes_sent.fetch_add(part.content_length, Ordering::Relaxed);
+            }
+        }
+
+        let shared = Arc::new(SharedInfo {
             large,
             part: AtomicU32::new(0),
+            completed,
             path: info.path.to_owned(),
             encryption: info.encryption.clone(),
+            progress: Arc::new(progress),
         });
 
-        // inject the old file handle for the first iteration
-        let old_files = stream::iter([Some(file)]).chain(stream::repeat_with(|| None));
+        shared.progress.report(None);
 
-        // use the old file handle for the first iteration, then open a new one for the rest and get the upload URL
-        let files_and_urls = old_files.take(max_simultaneous_uploads).then(|old_file| async {
-            let (url, file) = tokio::try_join!(info.large.get_upload_part_url(), async {
-                Ok(match old_file {
-                    Some(file) => file,
-                    None => File::open(&info.path).await?,
-                })
-            })?;
+        let mut tasks = JoinSet::new();
 
-            Ok::<_, B2Error>((info.clone(), Arc::new(Mutex::new(file)), url))
-        });
+        // inject the old file handle for the first task, the rest open their own
+        let mut old_file = Some(file);
+
+        for _ in 0..max_simultaneous_uploads {
+            let (shared, old_file) = (shared.clone(), old_file.take());
 
-        // for each file/url pair, upload the parts in parallel
-        let do_uploads = files_and_urls.map_ok(|(info, file, mut url)| async move {
             // spawn in new task for real parallelism, at least when using the multi-threaded runtime
-            let parts = tokio::spawn(async move {
+            tasks.spawn(async move {
+                let res = tokio::try_join!(shared.large.get_upload_part_url(), async {
+                    Ok(match old_file {
+                        Some(file) => file,
+                        None => File::open(&shared.path).await?,
+                    })
+                });
+
+                let (mut url, file) = match res {
+                    Ok(res) => res,
+                    Err(e) => return (Vec::new(), Some(e)),
+                };
+
+                let file = Arc::new(Mutex::new(file));
+
                 let mut parts = Vec::new();
 
                 loop {
-                    let part_number = info.part.fetch_add(1, Ordering::Relaxed);
+                    let part_number = shared.part.fetch_add(1, Ordering::Relaxed);
 
                     if part_number as u64 >= num_parts {
                         break;
                     }
 
-                    let start = part_number as u64 * recommended_part_size;
-                    let end = (start + recommended_part_size).min(length);
-
-                    let sha1 = {
-                        let mut file = file.try_lock().expect("Unable to lock file");
+                    if shared.completed[part_number as usize] {
+                        continue;
+                    }
 
-                        hash_chunk(&mut file, start, end).await?
-                    };
+                    let start = part_number as u64 * part_size;
+                    let end = (start + part_size).min(length);
 
                     let part_info = NewPartInfo {
-                        content_sha1: &sha1,
+                        content_sha1: ContentSha1::Compute,
                         content_length: end - start,
                         // SAFETY: part_number is unsigned, adding 1 will never be 0
                         part_number: unsafe { NonZeroU32::new_unchecked(part_number + 1) },
-                        encryption: info.encryption.clone(),
+                        encryption: shared.encryption.clone(),
                     };
 
-                    let part = FileChunk::new(file.clone(), start, end);
-                    let part = info.large.upload_part(&mut url, &part_info, part).await?;
+                    let part = FileChunk::new(file.clone(), part_number + 1, start, end, shared.progress.clone());
+                    let part = shared.large.upload_part(&mut url, &part_info, part).await;
 
-                    parts.push(Ok::<_, B2Error>(part));
+                    match part {
+                        Ok(part) => {
+                            shared.progress.part_completed(part_number + 1, end - start);
+                            parts.push(part);
+                        }
+                        // return any completed parts with the error, so they can be resumed
+                        Err(e) => return (parts, Some(e)),
+                    }
                 }
 
-                Ok::<_, B2Error>(stream::iter(parts))
+                (parts, None)
             });
-
-            parts.await.expect("Unable to upload") // only really happens if panic occurs
-        });
+        }
 
         // Box the future to avoid bloating the stack too much, especially with large DEFAULT_BUF_SIZE
-        let mut parts = Box::pin(do_uploads)
-            .try_buffer_unordered(max_simultaneous_uploads)
-            .try_flatten_unordered(max_simultaneous_uploads)
-            .try_collect::<Vec<_>>()
-            .await?;
+        let res = Box::pin(async {
+            loop {
+                let next = tokio::select! {
+                    biased;
+                    _ = cancelled(info.cancel.as_ref()) => return Err(B2Error::Cancelled),
+                    next = tasks.join_next() => next,
+                };
+
+                match next {
+                    None => return Ok(()),
+                    Some(Ok((mut task_parts, error))) => {
+                        parts.append(&mut task_parts);
+
+                        if let Some(e) = error {
+                            return Err(e);
+                        }
+                    }
+                    Some(Err(e)) => std::panic::resume_unwind(e.into_panic()), // tasks are never aborted here
+                }
+            }
+        })
+        .await;
+
+        // abort any parts still in-flight and wait for them, so the shared info is no longer shared
+        tasks.shutdown().await;
+
+        let shared = Arc::into_inner(shared).expect("all upload tasks have finished");
 
         parts.sort_unstable_by_key(|part| part.part_number);
+        parts.dedup_by_key(|part| part.part_number);
 
-        // SAFETY: done sharing the info now, can safely unwrap it
-        let info = unsafe { Arc::try_unwrap(info).unwrap_unchecked() };
+        let file_id = shared.large.info().file_id.clone();
 
-        info.large.finish(&parts).boxed().await
+        let incomplete = move |parts, error| {
+            B2Error::IncompleteLargeFile(Box::new(IncompleteLargeFile {
+                file_id,
+                part_size,
+                parts,
+                error,
+            }))
+        };
+
+        if let Err(e) = res {
+            if info.resumable {
+                return Err(incomplete(parts, e));
+            }
+
+            return Err(match shared.large.cancel().boxed().await {
+                Ok(_) => e,
+                // could not clean up, so let the caller know about the unfinished large file
+                Err(_) => incomplete(parts, e),
+            });
+        }
+
+        match shared.large.finish(&parts).boxed().await {
+            Ok(file) => Ok(file),
+            Err(e) => Err(incomplete(parts, e)),
+        }
     }
 }
@@ -0,0 +1,260 @@
+//! Uploading whole directory trees.
+
+use std::path::{Path, PathBuf};
+
+use futures_util::stream::{self, StreamExt};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+
+use crate::pool::Pool;
+use crate::*;
+
+/// Options for uploading a directory with [`Client::upload_dir`].
+///
+/// See the documentation for [`UploadDirOptions::builder`] for more information.
+#[derive(Debug, typed_builder::TypedBuilder)]
+#[builder(doc)]
+pub struct UploadDirOptions<'a> {
+    /// The ID of the bucket to upload to. If `None`, the client's default bucket will be used.
+    #[builder(default, setter(into))]
+    pub bucket_id: Option<&'a str>,
+
+    /// Glob patterns for files to include, matched against the path relative to the local root,
+    /// using `/` as the separator.
+    ///
+    /// If empty, all files are included.
+    #[builder(default, setter(into))]
+    pub include: Vec<&'a str>,
+
+    /// Glob patterns for files to exclude, matched against the path relative to the local root,
+    /// using `/` as the separator.
+    ///
+    /// Exclusions take precedence over inclusions.
+    #[builder(default, setter(into))]
+    pub exclude: Vec<&'a str>,
+
+    /// The maximum number of files to upload at once.
+    ///
+    /// If set to 0, the default of 4 files will be used.
+    #[builder(default)]
+    pub max_simultaneous_files: u8,
+
+    /// The maximum number of connections to use for each large file,
+    /// see [`NewFileFromPath::max_simultaneous_uploads`].
+    #[builder(default)]
+    pub max_simultaneous_uploads: u8,
+
+    /// The MIME type to use for all files.
+    ///
+    /// Use `b2/x-auto` to have B2 determine the content type from each file's extension.
+    #[builder(default, setter(into))]
+    pub content_type: Option<&'a str>,
+
+    /// The server-side encryption to use when uploading the files.
+    #[builder(default)]
+    pub encryption: sse::ServerSideEncryption,
+}
+
+/// The results of [`Client::upload_dir`], for each file that was found.
+#[derive(Default, Debug)]
+pub struct UploadDirReport {
+    /// Local paths of files that were uploaded, and their new B2 file info.
+    pub uploaded: Vec<(PathBuf, models::B2FileInfo)>,
+
+    /// Local paths of files or directories that failed, and the error that occurred.
+    pub failed: Vec<(PathBuf, B2Error)>,
+}
+
+impl UploadDirReport {
+    /// Returns `true` if every file was uploaded successfully.
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+/// Include and exclude glob patterns for paths relative to a root, using `/` as the separator.
+pub(crate) struct PathFilter {
+    include: Option<GlobSet>,
+    exclude: Option<GlobSet>,
+}
+
+impl PathFilter {
+    pub fn new(include: &[&str], exclude: &[&str]) -> Result<Self, B2Error> {
+        fn build_globset(patterns: &[&str]) -> Result<Option<GlobSet>, B2Error> {
+            if patterns.is_empty() {
+                return Ok(None);
+            }
+
+            let mut builder = GlobSetBuilder::new();
+
+            for pattern in patterns {
+                builder.add(Glob::new(pattern)?);
+            }
+
+            Ok(Some(builder.build()?))
+        }
+
+        Ok(PathFilter {
+            include: build_globset(include)?,
+            exclude: build_globset(exclude)?,
+        })
+    }
+
+    /// Returns `true` if the relative path is included and not excluded.
+    pub fn is_match(&self, relative: &str) -> bool {
+        if self.include.as_ref().is_some_and(|include| !include.is_match(relative)) {
+            return false;
+        }
+
+        !self.exclude.as_ref().is_some_and(|exclude| exclude.is_match(relative))
+    }
+}
+
+/// A file found while walking the local directory.
+pub(crate) struct LocalFile {
+    /// The full local path of the file.
+    pub path: PathBuf,
+
+    /// The path relative to the local root, using `/` as the separator.
+    pub relative: String,
+
+    /// The metadata of the file, following symlinks.
+    pub metadata: std::fs::Metadata,
+}
+
+/// Recursively lists all files under `root`, filtered by the given globs.
+///
+/// Symlinks to files are followed, but symlinks to directories are not, to avoid cycles.
+///
+/// Errors reading the root directory are returned, while errors for anything below it are collected.
+pub(crate) async fn walk_dir(
+    root: &Path,
+    filter: &PathFilter,
+) -> Result<(Vec<LocalFile>, Vec<(PathBuf, B2Error)>), B2Error> {
+    let mut files = Vec::new();
+    let mut failed = Vec::new();
+
+    let mut dirs = vec![(tokio::fs::read_dir(root).await?, String::new())];
+
+    while let Some((mut entries, relative_dir)) = dirs.pop() {
+        loop {
+            let entry = match entries.next_entry().await {
+                Ok(Some(entry)) => entry,
+                Ok(None) => break,
+                Err(e) => {
+                    failed.push((root.join(&relative_dir), e.into()));
+                    break;
+                }
+            };
+
+            let path = entry.path();
+            let relative = format!("{relative_dir}{}", entry.file_name().to_string_lossy());
+
+            let res = async {
+                let file_type = entry.file_type().await?;
+
+                if file_type.is_dir() {
+                    dirs.push((tokio::fs::read_dir(&path).await?, format!("{relative}/")));
+                    return Ok(None);
+                }
+
+                // follows symlinks
+                let metadata = tokio::fs::metadata(&path).await?;
+
+                if !metadata.is_file() {
+                    return Ok(None);
+                }
+
+                Ok::<_, B2Error>(Some(metadata))
+            };
+
+            match res.await {
+                Ok(Some(metadata)) if filter.is_match(&relative) => {
+                    files.push(LocalFile {
+                        path,
+                        relative,
+                        metadata,
+                    });
+                }
+                Ok(_) => {}
+                Err(e) => failed.push((path, e)),
+            }
+        }
+    }
+
+    Ok((files, failed))
+}
+
+impl Client {
+    /// Uploads all files under `local_root` to B2, naming each file by its path relative to
+    /// `local_root` with `remote_prefix` prepended as-is. Include a trailing `/` in `remote_prefix`
+    /// to upload into a "folder".
+    ///
+    /// Files are uploaded concurrently, reusing upload URLs from a [`Pool`], and each file is uploaded
+    /// with [`Client::upload_from_path`], so large files are uploaded in parts.
+    ///
+    /// An error is only returned if the options are invalid or `local_root` cannot be read,
+    /// otherwise failures are collected for each file in the returned [`UploadDirReport`].
+    pub async fn upload_dir(
+        &self,
+        local_root: &Path,
+        remote_prefix: &str,
+        options: &UploadDirOptions<'_>,
+    ) -> Result<UploadDirReport, B2Error> {
+        let filter = PathFilter::new(&options.include, &options.exclude)?;
+        let (files, failed) = walk_dir(local_root, &filter).await?;
+
+        let max_simultaneous_files = match options.max_simultaneous_files {
+            0 => 4,
+            n => n,
+        };
+
+        let recommended_part_size = self.state.read().await.account.api.storage.recommended_part_size;
+
+        let pool = Pool::new(self.clone(), options.bucket_id, max_simultaneous_files);
+
+        let mut report = UploadDirReport {
+            uploaded: Vec::with_capacity(files.len()),
+            failed,
+        };
+
+        let uploads = stream::iter(files).map(|file| {
+            let pool = &pool;
+
+            async move {
+                let file_name = format!("{remote_prefix}{}", file.relative);
+
+                let info = NewFileFromPath::builder()
+                    .path(&file.path)
+                    .file_name(file_name.as_str())
+                    .content_type(options.content_type)
+                    .max_simultaneous_uploads(options.max_simultaneous_uploads)
+                    .encryption(options.encryption.clone())
+                    .build();
+
+                // large files get their own upload part URLs
+                let res = if file.metadata.len() <= recommended_part_size {
+                    match pool.get_pooled_upload_url().await {
+                        Ok(mut url) => self.upload_from_path(&info, options.bucket_id, Some(&mut url)).await,
+                        Err(e) => Err(e),
+                    }
+                } else {
+                    self.upload_from_path(&info, options.bucket_id, None).await
+                };
+
+                (file.path, res)
+            }
+        });
+
+        // Box the future to avoid bloating the stack too much, especially with large DEFAULT_BUF_SIZE
+        let mut uploads = Box::pin(uploads.buffer_unordered(max_simultaneous_files as usize));
+
+        while let Some((path, res)) = uploads.next().await {
+            match res {
+                Ok(info) => report.uploaded.push((path, info)),
+                Err(e) => report.failed.push((path, e)),
+            }
+        }
+
+        Ok(report)
+    }
+}
@@ -15,10 +15,16 @@
 //!
 //! ## Cargo Features
 //!
-//! - `fs` (enables optimized routine for uploading from filesystem)
+//! - `fs` (enables optimized routines for uploading files and directories from filesystem, implies `pool`)
 //! - `pool` (enabled non-large `UploadURL` object pool for reuse)
 //! - `reqwest_compression` (enables deflate/gzip features on `reqwest`)
 //! - `large_buffers` (enables large buffer support, 64KiB instead of 8KiB)
+//! - `object_store` (implements `object_store::ObjectStore` for a bucket with `B2ObjectStore`)
+//! - `blocking` (enables the synchronous `blocking::Client`, driving an internal tokio runtime)
+//! - `cli` (builds the `yab2` command-line binary, credentials are read from `B2_APPLICATION_KEY_ID` and `B2_APPLICATION_KEY`, or the `b2` CLI's account info)
+//! - `b2_account_info` (enables loading credentials from the official `b2` CLI's `~/.b2_account_info` with `ClientBuilder::from_account_info`)
+//! - `tracing` (emits `tracing` spans for each B2 operation, and events on reauthorization and upload URL refreshes)
+//! - `metrics` (enables `metrics::MetricsRecorder`, reporting requests, bytes, latencies and transaction classes to the `metrics` crate)
 //!
 //! ## **WARNING**
 //!
@@ -40,7 +46,7 @@ use headers::HeaderMapExt;
 use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
 use reqwest::Method;
 use smol_str::SmolStr;
-use tokio::sync::RwLock;
+use tokio::sync::{Mutex, RwLock};
 
 macro_rules! h {
     ($headers:ident.$key:literal => $value:expr) => {
@@ -51,29 +57,45 @@ macro_rules! h {
     };
 }
 
+mod checksum;
+mod copy;
+mod limit;
+mod stall;
 mod types;
 
+#[cfg(feature = "tracing")]
+mod trace;
+
+pub mod credentials;
 pub mod error;
+pub mod metrics;
 pub mod models;
+pub mod registry;
+pub mod replay;
+pub mod token_cache;
+pub mod transport;
 
 pub use types::sse;
 pub use types::{
-    CreateApplicationKey, CreateBucket, DownloadFileBy, FileRetention, ListBuckets, ListFiles, NewFileInfo,
-    NewLargeFileInfo, NewPartInfo, UpdateBucket,
+    ContentSha1, CopyFile, CopyPart, CreateApplicationKey, CreateBucket, DownloadFileBy, FileRetention,
+    ListBuckets, ListFiles, MetadataDirective, NewFileInfo, NewLargeFileInfo, NewPartInfo, UpdateBucket,
 };
 
 #[cfg(feature = "fs")]
-pub use fs::NewFileFromPath;
+pub use fs::{
+    NewFileFromPath, PartProgress, UploadDirOptions, UploadDirReport, UploadProgress, UploadProgressSink,
+};
 
 /// Autogenerated builders for various types.
 pub mod builders {
     pub use crate::types::{
-        CreateApplicationKeyBuilder, CreateBucketBuilder, FileRetentionBuilder, ListBucketsBuilder,
-        ListFilesBuilder, NewFileInfoBuilder, NewLargeFileInfoBuilder, NewPartInfoBuilder, UpdateBucketBuilder,
+        CopyFileBuilder, CopyPartBuilder, CreateApplicationKeyBuilder, CreateBucketBuilder, FileRetentionBuilder,
+        ListBucketsBuilder, ListFilesBuilder, NewFileInfoBuilder, NewLargeFileInfoBuilder, NewPartInfoBuilder,
+        UpdateBucketBuilder,
     };
 
     #[cfg(feature = "fs")]
-    pub use crate::fs::NewFileFromPathBuilder;
+    pub use crate::fs::{NewFileFromPathBuilder, UploadDirOptionsBuilder};
 }
 
 #[cfg(feature = "pool")]
@@ -82,6 +104,18 @@ pub mod pool;
 #[cfg(feature = "fs")]
 mod fs;
 
+#[cfg(feature = "fs")]
+pub mod sync;
+
+pub mod bulk;
+pub mod migrate;
+
+#[cfg(feature = "object_store")]
+pub mod object_store;
+
+#[cfg(feature = "blocking")]
+pub mod blocking;
+
 pub use error::B2Error;
 
 use models::capabilities::{B2CapabilitiesStringSet, B2Capability};
@@ -95,9 +129,43 @@ struct ClientState {
 
     /// The authorization header to use for requests
     auth: HeaderValue,
+
+    /// When the account was authorized, in milliseconds since the Unix epoch
+    authorized_at: u64,
+
+    /// Incremented each time the client is reauthorized
+    generation: u64,
 }
 
 impl ClientState {
+    fn new(
+        config: ClientBuilder,
+        account: models::B2Authorized,
+        authorized_at: u64,
+    ) -> Result<ClientState, B2Error> {
+        Ok(ClientState {
+            config,
+            auth: HeaderValue::from_str(&account.auth_token).map_err(|_| B2Error::InvalidAuthToken)?,
+            account,
+            authorized_at,
+            generation: 0,
+        })
+    }
+
+    /// When the authorization should be refreshed, `margin` before the token expires,
+    /// in milliseconds since the Unix epoch.
+    ///
+    /// Returns `None` if the application key expires before the token,
+    /// as the token cannot be refreshed past the key's expiration.
+    fn refresh_at(&self, margin: Duration) -> Option<u64> {
+        let token_expires = self.authorized_at + token_cache::MAX_TOKEN_AGE.as_millis() as u64;
+
+        match self.account.expiration {
+            Some(key_expires) if key_expires <= token_expires => None,
+            _ => Some(token_expires.saturating_sub(margin.as_millis() as u64)),
+        }
+    }
+
     fn check_capability(&self, capability: B2Capability) -> Result<(), B2Error> {
         if !self.account.allowed(capability) {
             return Err(B2Error::MissingCapability(capability));
@@ -128,25 +196,96 @@ impl ClientState {
 #[derive(Clone)]
 pub struct Client {
     state: Arc<RwLock<ClientState>>,
-    client: reqwest::Client,
+    transport: Arc<dyn transport::HttpTransport>,
+
+    /// Held while reauthorizing, so concurrent callers wait for a single reauthorization.
+    reauth: Arc<Mutex<()>>,
+
+    timeouts: Timeouts,
+    metrics: Option<Arc<dyn metrics::Metrics>>,
+    limiter: Option<Arc<limit::Limiter>>,
 }
 
 /// A builder for creating a [`Client`]
 #[derive(Clone)]
 pub struct ClientBuilder {
+    key_id: SmolStr,
     auth: HeaderValue,
     ua: Option<Cow<'static, str>>,
     max_retries: u8,
     retry_delay: Duration,
+    token_cache: Option<Arc<dyn token_cache::TokenCache>>,
+    refresh_before: Option<Duration>,
+    http: Option<reqwest::Client>,
+    transport: Option<Arc<dyn transport::HttpTransport>>,
+    configure_http: Option<Arc<ConfigureHttp>>,
+    timeouts: Timeouts,
+    metrics: Option<Arc<dyn metrics::Metrics>>,
+    limits: limit::Limits,
+
+    /// Built from `limits` when authorizing, and shared with reauthorization.
+    limiter: Option<Arc<limit::Limiter>>,
 }
 
-/// Wrapper around a response and the file's parsed headers.
-pub struct DownloadedFile {
-    /// The streaming response
-    pub resp: reqwest::Response,
+type ConfigureHttp = dyn Fn(reqwest::ClientBuilder) -> reqwest::ClientBuilder + Send + Sync + 'static;
+
+/// Per-operation timeouts, see [`ClientBuilder::api_timeout`] and [`ClientBuilder::stall_timeout`].
+#[derive(Debug, Default, Clone, Copy)]
+struct Timeouts {
+    api: Option<Duration>,
+    stall: Option<Duration>,
+}
 
+/// Wrapper around a streaming response and the file's parsed headers.
+///
+/// The body is read with [`DownloadedFile::chunk`], [`DownloadedFile::bytes`] or [`DownloadedFile::bytes_stream`],
+/// independently of the [`HttpTransport`](transport::HttpTransport) that received it.
+pub struct DownloadedFile {
     /// Parsed header info from the response
     pub info: models::B2FileHeaders,
+
+    resp: http::Response<transport::ResponseBody>,
+}
+
+impl DownloadedFile {
+    /// The status of the response, such as `206 Partial Content` when downloading a range.
+    pub fn status(&self) -> reqwest::StatusCode {
+        self.resp.status()
+    }
+
+    /// The raw headers of the response.
+    pub fn headers(&self) -> &HeaderMap {
+        self.resp.headers()
+    }
+
+    /// Reads the next chunk of the body, or `None` once the whole body has been read.
+    pub async fn chunk(&mut self) -> Result<Option<bytes::Bytes>, B2Error> {
+        transport::next_chunk(self.resp.body_mut()).await
+    }
+
+    /// Reads the rest of the body into memory.
+    pub async fn bytes(self) -> Result<bytes::Bytes, B2Error> {
+        use http_body_util::BodyExt;
+
+        Ok(self.resp.into_body().collect().await.map_err(transport::body_error)?.to_bytes())
+    }
+
+    /// Returns a stream of the chunks of the body.
+    pub fn bytes_stream(self) -> impl futures_util::Stream<Item = Result<bytes::Bytes, B2Error>> + Send + 'static {
+        use futures_util::TryStreamExt;
+
+        http_body_util::BodyDataStream::new(self.resp.into_body()).map_err(transport::body_error)
+    }
+
+    /// Returns the body, such as to pass to another HTTP client.
+    pub fn into_body(self) -> transport::ResponseBody {
+        self.resp.into_body()
+    }
+
+    /// Converts the download into a [`reqwest::Response`], for code written against `reqwest`.
+    pub fn into_reqwest(self) -> reqwest::Response {
+        reqwest::Response::from(self.resp.map(reqwest::Body::wrap))
+    }
 }
 
 impl ClientBuilder {
@@ -155,10 +294,20 @@ impl ClientBuilder {
     /// **NOTE**: The account ID can be used in place of the master application key ID.
     pub fn new(key_id: &str, app_key: &str) -> ClientBuilder {
         ClientBuilder {
+            key_id: SmolStr::from(key_id),
             auth: models::create_auth_header(key_id, app_key),
             ua: None,
             max_retries: 5,
             retry_delay: Duration::from_secs(1),
+            token_cache: None,
+            refresh_before: None,
+            http: None,
+            transport: None,
+            configure_http: None,
+            timeouts: Timeouts::default(),
+            metrics: None,
+            limits: limit::Limits::default(),
+            limiter: None,
         }
     }
 
@@ -182,20 +331,197 @@ impl ClientBuilder {
         self
     }
 
+    /// Sets a cache for authorization tokens, to reuse a recent authorization for the same key ID
+    /// instead of calling `b2_authorize_account`, and store new authorizations.
+    ///
+    /// See the [`token_cache`] module for more information.
+    pub fn token_cache(mut self, cache: impl token_cache::TokenCache) -> Self {
+        self.token_cache = Some(Arc::new(cache));
+        self
+    }
+
+    /// Enables refreshing the authorization in a background task `margin` before the token expires,
+    /// instead of only after a request fails with 401 Unauthorized.
+    ///
+    /// The task stops once every clone of the client has been dropped, if the application key
+    /// expires before the token, as the authorization cannot be refreshed past the key's expiration,
+    /// or if refreshing is refused with 400, 401 or 403, such as when the key has been deleted.
+    /// Other failures are retried after a delay.
+    ///
+    /// **NOTE**: The task is spawned on the tokio runtime that [`ClientBuilder::authorize`] is called from.
+    /// The runtime of a blocking `Client` only runs while a blocking call is in progress,
+    /// so the task will not refresh in between calls there.
+    pub fn refresh_before(mut self, margin: Duration) -> Self {
+        self.refresh_before = Some(margin);
+        self
+    }
+
+    /// Reports requests, retries and reauthorizations to the given [`Metrics`](metrics::Metrics),
+    /// see the [`metrics`](crate::metrics) module for more information.
+    pub fn metrics(mut self, metrics: impl metrics::Metrics) -> Self {
+        self.metrics = Some(Arc::new(metrics));
+        self
+    }
+
+    /// Limits requests of the given [transaction class](metrics::TransactionClass) to `per_second`,
+    /// waiting before sending requests that would exceed it.
+    ///
+    /// Limits are shared by every clone of the client, including any [`Pool`](crate::pool::Pool) created from it.
+    pub fn rate_limit(mut self, class: metrics::TransactionClass, per_second: std::num::NonZeroU32) -> Self {
+        self.limits.set_rate(class, per_second);
+        self
+    }
+
+    /// Limits the number of requests in flight at once, waiting before sending requests that would exceed it.
+    ///
+    /// A request is in flight until its response headers are received, so uploads count while their body is sent,
+    /// but downloads do not count while their [`DownloadedFile`] is being read. Otherwise a download held open
+    /// while streaming it into an upload, such as by [`Client::migrate`], could wait forever for its own upload.
+    pub fn max_in_flight(mut self, max: std::num::NonZeroUsize) -> Self {
+        self.limits.max_in_flight = Some(max);
+        self
+    }
+
+    /// Limits the combined bandwidth of upload and download bodies to `bytes_per_second`,
+    /// across every clone of the client.
+    pub fn bandwidth_limit(mut self, bytes_per_second: std::num::NonZeroU32) -> Self {
+        self.limits.bandwidth = Some(bytes_per_second);
+        self
+    }
+
+    /// Uses an existing HTTP client for all requests, such as to share its connection pool
+    /// with the rest of an application.
+    ///
+    /// [`ClientBuilder::user_agent`] and [`ClientBuilder::configure_http`] are ignored, and downloads are
+    /// not subject to [`ClientBuilder::stall_timeout`] unless the client has a `read_timeout` set.
+    pub fn http_client(mut self, client: reqwest::Client) -> Self {
+        self.http = Some(client);
+        self
+    }
+
+    /// Sends all requests through a custom [`HttpTransport`](transport::HttpTransport) instead of `reqwest`,
+    /// taking precedence over [`ClientBuilder::http_client`].
+    ///
+    /// [`ClientBuilder::user_agent`], [`ClientBuilder::configure_http`] and the download stall timeout
+    /// are not applied, see the [`transport`] module for more information.
+    pub fn transport(mut self, transport: impl transport::HttpTransport) -> Self {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// Customizes the HTTP client built by [`ClientBuilder::authorize`], such as to set proxies,
+    /// root certificates, connect timeouts, HTTP/2 preferences or connection pool limits.
+    ///
+    /// The closure is given a builder with HTTPS-only mode, the user agent and the stall timeout already set.
+    ///
+    /// ```ignore
+    /// let client = ClientBuilder::new(&key_id, &key)
+    ///     .configure_http(|http| http.connect_timeout(Duration::from_secs(5)).pool_max_idle_per_host(16))
+    ///     .authorize()
+    ///     .await?;
+    /// ```
+    pub fn configure_http(
+        mut self,
+        f: impl Fn(reqwest::ClientBuilder) -> reqwest::ClientBuilder + Send + Sync + 'static,
+    ) -> Self {
+        self.configure_http = Some(Arc::new(f));
+        self
+    }
+
+    /// Sets the total timeout for API calls, such as listing files or authorizing the account.
+    ///
+    /// Uploads and downloads are not subject to this timeout, see [`ClientBuilder::stall_timeout`].
+    pub fn api_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.api = Some(timeout);
+        self
+    }
+
+    /// Sets how long uploads and downloads may go without making progress before failing,
+    /// regardless of how long they take in total.
+    ///
+    /// Uploads fail with [`B2Error::Stalled`] if no part of the body is sent for this long, or the response
+    /// takes this long once the body has been sent. Downloads fail with a [`B2Error::ReqwestError`] timeout
+    /// if no part of the response is received for this long.
+    pub fn stall_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.stall = Some(timeout);
+        self
+    }
+
     /// Builds and authorizes the client for first use.
-    pub async fn authorize(self) -> Result<Client, B2Error> {
+    ///
+    /// If a [token cache](ClientBuilder::token_cache) is set and has a recent authorization
+    /// for the same key ID, it will be used without calling `b2_authorize_account`.
+    pub async fn authorize(mut self) -> Result<Client, B2Error> {
+        let transport = match self.take_transport() {
+            Some(transport) => transport,
+            None => Arc::new(transport::ReqwestTransport::new(self.build_http()?)),
+        };
+
+        self.authorize_with(transport).await
+    }
+
+    /// Takes the custom transport or HTTP client, if any.
+    pub(crate) fn take_transport(&mut self) -> Option<Arc<dyn transport::HttpTransport>> {
+        match self.transport.take() {
+            Some(transport) => Some(transport),
+            None => Some(Arc::new(transport::ReqwestTransport::new(self.http.take()?))),
+        }
+    }
+
+    fn build_http(&self) -> Result<reqwest::Client, B2Error> {
         let mut builder = reqwest::ClientBuilder::new().https_only(true);
 
         if let Some(ref ua) = self.ua {
             builder = builder.user_agent(ua.as_ref());
         }
 
-        let client = builder.build()?;
+        if let Some(stall) = self.timeouts.stall {
+            builder = builder.read_timeout(stall);
+        }
+
+        if let Some(ref configure) = self.configure_http {
+            builder = configure(builder);
+        }
 
-        Ok(Client {
-            state: Arc::new(RwLock::new(Client::do_auth(&client, self).await?)),
-            client,
-        })
+        Ok(builder.build()?)
+    }
+
+    /// Authorizes the client using an existing transport, ignoring [`ClientBuilder::user_agent`].
+    pub(crate) async fn authorize_with(
+        mut self,
+        transport: Arc<dyn transport::HttpTransport>,
+    ) -> Result<Client, B2Error> {
+        self.limiter = self.limits.build();
+
+        let cached = match self.token_cache {
+            Some(ref cache) => token_cache::CachedToken::load(&**cache, &self.key_id),
+            None => None,
+        };
+
+        let refresh_before = self.refresh_before;
+        let timeouts = self.timeouts;
+        let metrics = self.metrics.clone();
+        let limiter = self.limiter.clone();
+
+        let state = match cached {
+            Some((account, obtained_at)) => ClientState::new(self, account, obtained_at)?,
+            None => Client::do_auth(&transport, self).await?,
+        };
+
+        let client = Client {
+            state: Arc::new(RwLock::new(state)),
+            transport,
+            reauth: Arc::default(),
+            timeouts,
+            metrics,
+            limiter,
+        };
+
+        if let Some(margin) = refresh_before {
+            client.spawn_refresh(margin);
+        }
+
+        Ok(client)
     }
 }
 
@@ -203,29 +529,48 @@ struct DummyValue;
 
 impl<'de> serde::Deserialize<'de> for DummyValue {
     #[inline(always)]
-    fn deserialize<D>(_: D) -> Result<Self, D::Error>
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: serde::Deserializer<'de>,
     {
+        // the value must still be consumed, or `serde_json` fails on the trailing characters
+        serde::de::IgnoredAny::deserialize(deserializer)?;
+
         Ok(DummyValue)
     }
 }
 
 impl Client {
-    fn req(&self, method: Method, auth: &HeaderValue, url: impl AsRef<str>) -> reqwest::RequestBuilder {
-        self.client.request(method, url.as_ref()).header(AUTHORIZATION, auth)
+    /// Builds an API request, subject to [`ClientBuilder::api_timeout`].
+    fn req(&self, method: Method, auth: &HeaderValue, url: impl AsRef<str>) -> transport::RequestBuilder {
+        let builder = self.transfer_req(method, auth, url);
+
+        match self.timeouts.api {
+            Some(timeout) => builder.timeout(timeout),
+            None => builder,
+        }
+    }
+
+    /// Builds an upload or download request, which has no total timeout.
+    fn transfer_req(&self, method: Method, auth: &HeaderValue, url: impl AsRef<str>) -> transport::RequestBuilder {
+        transport::RequestBuilder::new(self.transport.clone(), method, url.as_ref())
+            .metrics(self.metrics.clone())
+            .limiter(self.limiter.clone())
+            .header(AUTHORIZATION, auth)
     }
 
-    async fn json<T>(builder: reqwest::RequestBuilder) -> Result<T, B2Error>
+    async fn json<T>(builder: transport::RequestBuilder) -> Result<T, B2Error>
     where
         T: serde::de::DeserializeOwned,
     {
+        use http_body_util::BodyExt;
+
         let resp = builder.send().await?;
 
         let is_error = !resp.status().is_success();
 
         // only get the body once to avoid multiple awaits
-        let body = resp.bytes().await?;
+        let body = resp.into_body().collect().await.map_err(transport::body_error)?.to_bytes();
 
         if is_error {
             return Err(B2Error::B2ErrorMessage(serde_json::from_slice(&body)?));
@@ -234,28 +579,55 @@ impl Client {
         Ok(serde_json::from_slice(&body)?)
     }
 
-    async fn do_auth(client: &reqwest::Client, config: ClientBuilder) -> Result<ClientState, B2Error> {
+    async fn do_auth(
+        transport: &Arc<dyn transport::HttpTransport>,
+        config: ClientBuilder,
+    ) -> Result<ClientState, B2Error> {
         use failsafe::{futures::CircuitBreaker, Config, Error as FailsafeError};
 
         let cb = Config::new().build();
         let mut attempts = 0;
 
+        #[cfg(feature = "tracing")]
+        let span = trace::op_span();
+
         'try_auth: loop {
-            let do_auth_inner = Client::json::<models::B2Authorized>(
-                client
-                    .get("https://api.backblazeb2.com/b2api/v3/b2_authorize_account")
-                    .header(AUTHORIZATION, &config.auth),
-            );
+            let mut req = transport::RequestBuilder::new(
+                transport.clone(),
+                Method::GET,
+                "https://api.backblazeb2.com/b2api/v3/b2_authorize_account",
+            )
+            .metrics(config.metrics.clone())
+            .limiter(config.limiter.clone())
+            .header(AUTHORIZATION, &config.auth);
+
+            if let Some(timeout) = config.timeouts.api {
+                req = req.timeout(timeout);
+            }
+
+            let do_auth_inner = Client::json::<models::B2Authorized>(req);
+
+            #[cfg(feature = "tracing")]
+            let do_auth_inner =
+                tracing::Instrument::instrument(do_auth_inner, span.record("attempt", attempts + 1).clone());
 
             return match cb.call(do_auth_inner).await {
-                Ok(account) => Ok(ClientState {
-                    config,
-                    auth: HeaderValue::from_str(&account.auth_token)
-                        .expect("Unable to use auth token in header value"),
-                    account,
-                }),
+                Ok(account) => {
+                    if let Some(ref cache) = config.token_cache {
+                        token_cache::CachedToken::store(&**cache, &config.key_id, &account);
+                    }
+
+                    ClientState::new(config, account, token_cache::now_millis())
+                }
                 Err(FailsafeError::Rejected) => {
                     attempts += 1;
+
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(parent: &span, attempts, "authorization rejected by circuit breaker");
+
+                    if let Some(ref metrics) = config.metrics {
+                        metrics.retry();
+                    }
                     if attempts >= config.max_retries {
                         return Err(B2Error::Unauthorized);
                     }
@@ -269,26 +641,120 @@ impl Client {
         }
     }
 
-    /// Reauthorizes the client, updating the authorization token and account information.
-    async fn reauthorize(&self) -> Result<(), B2Error> {
-        let new_state = Self::do_auth(&self.client, self.state.read().await.config.clone()).await?;
-        *self.state.write().await = new_state;
+    /// Reauthorizes the client, updating the authorization token and account information,
+    /// unless it has already been reauthorized since generation `seen` of the state.
+    async fn reauthorize(&self, seen: u64) -> Result<(), B2Error> {
+        Self::reauthorize_state(&self.transport, &self.state, &self.reauth, seen).await
+    }
+
+    /// Single-flight reauthorization: concurrent callers that saw the same generation wait for
+    /// one call to `b2_authorize_account`, then retry with the new token.
+    async fn reauthorize_state(
+        transport: &Arc<dyn transport::HttpTransport>,
+        state: &RwLock<ClientState>,
+        reauth: &Mutex<()>,
+        seen: u64,
+    ) -> Result<(), B2Error> {
+        let _guard = reauth.lock().await;
+
+        let config = {
+            let state = state.read().await;
+
+            // another caller already reauthorized while we waited
+            if state.generation != seen {
+                return Ok(());
+            }
+
+            state.config.clone()
+        };
+
+        // authorize without holding the state lock, so requests continue with the current token until swapped
+        let mut new_state = Self::do_auth(transport, config).await?;
+        new_state.generation = seen + 1;
+
+        if let Some(ref metrics) = new_state.config.metrics {
+            metrics.reauthorized();
+        }
+
+        *state.write().await = new_state;
+
         Ok(())
     }
 
+    /// Spawns a task to reauthorize the client `margin` before the token expires,
+    /// see [`ClientBuilder::refresh_before`].
+    fn spawn_refresh(&self, margin: Duration) {
+        /// Delay before trying again if refreshing fails.
+        const RETRY_DELAY: Duration = Duration::from_secs(60);
+
+        let state = Arc::downgrade(&self.state);
+        let transport = self.transport.clone();
+        let reauth = self.reauth.clone();
+
+        tokio::spawn(async move {
+            loop {
+                // recomputed each time, as the state may have been reauthorized after a 401 in the meantime
+                let (refresh_at, generation) = match state.upgrade() {
+                    Some(state) => {
+                        let state = state.read().await;
+
+                        match state.refresh_at(margin) {
+                            Some(refresh_at) => (refresh_at, state.generation),
+                            None => return,
+                        }
+                    }
+                    None => return,
+                };
+
+                let now = token_cache::now_millis();
+
+                if refresh_at > now {
+                    tokio::time::sleep(Duration::from_millis(refresh_at - now)).await;
+                }
+
+                let Some(state) = state.upgrade() else { return };
+
+                // does nothing if reauthorized after a 401 while sleeping
+                match Client::reauthorize_state(&transport, &state, &reauth, generation).await {
+                    Ok(()) => {}
+                    // retrying with the same key cannot succeed
+                    Err(B2Error::B2ErrorMessage(e)) if matches!(e.status, 400 | 401 | 403) => return,
+                    Err(_) => tokio::time::sleep(RETRY_DELAY).await,
+                }
+            }
+        });
+    }
+
     /// Runs a request, reauthorizing if necessary.
     async fn run_request_with_reauth<'a, F, R, T>(&self, f: F) -> Result<T, B2Error>
     where
         F: Fn(Self) -> R + 'a,
         R: Future<Output = Result<T, B2Error>> + 'a,
     {
+        #[cfg(feature = "tracing")]
+        let span = trace::op_span();
+
         let mut retried = false;
         loop {
-            return match f(self.clone()).await {
+            let generation = self.state.read().await.generation;
+
+            let res = f(self.clone());
+
+            #[cfg(feature = "tracing")]
+            let res = tracing::Instrument::instrument(res, span.record("attempt", retried as u8 + 1).clone());
+
+            return match res.await {
                 Ok(t) => Ok(t),
                 Err(B2Error::B2ErrorMessage(e)) if !retried && e.status == 401 => {
+                    #[cfg(feature = "tracing")]
+                    tracing::info!(parent: &span, generation, "reauthorizing after 401 Unauthorized");
+
                     // box future to avoid stack bloat
-                    Box::pin(self.reauthorize()).await?;
+                    Box::pin(self.reauthorize(generation)).await?;
+
+                    if let Some(ref metrics) = self.metrics {
+                        metrics.retry();
+                    }
 
                     retried = true;
                     continue;
@@ -588,6 +1054,33 @@ impl Client {
         .await
     }
 
+    /// Deletes the bucket specified by `bucket_id`, returning the deleted bucket.
+    ///
+    /// Only buckets that contain no file versions can be deleted.
+    pub async fn delete_bucket(&self, bucket_id: &str) -> Result<models::B2Bucket, B2Error> {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct B2DeleteBucket<'a> {
+            account_id: &'a str,
+            bucket_id: &'a str,
+        }
+
+        self.run_request_with_reauth(|b2| async move {
+            let state = b2.state.read().await;
+
+            state.check_capability(B2Capability::DELETE_BUCKETS)?;
+
+            Self::json(
+                b2.req(Method::POST, &state.auth, state.url("b2_delete_bucket")).json(&B2DeleteBucket {
+                    account_id: &state.account.account_id,
+                    bucket_id,
+                }),
+            )
+            .await
+        })
+        .await
+    }
+
     /// Uses the `b2_get_file_info` API to get information about a file by its ID.
     pub async fn get_file_info(&self, file_id: &str) -> Result<models::B2FileInfo, B2Error> {
         #[derive(Serialize)]
@@ -601,14 +1094,16 @@ impl Client {
 
             state.check_capability(B2Capability::READ_FILES)?; // TODO: check if this is the right capability
 
-            Client::json(b2.req(Method::GET, &state.auth, "b2_get_file_info").query(&B2GetFileInfo { file_id }))
-                .await
+            Client::json(
+                b2.req(Method::GET, &state.auth, state.url("b2_get_file_info")).query(&B2GetFileInfo { file_id }),
+            )
+            .await
         })
         .await
     }
 
     /// Downloads a file by its ID or name, returning a [`DownloadedFile`],
-    /// which is a wrapper around a [`reqwest::Response`] and the file's parsed headers.
+    /// which is a wrapper around the streaming response and the file's parsed headers.
     ///
     /// The `file` parameter can be either a file ID or a file name.
     /// The `range` parameter can be used to download only a portion of the file. If `None`, the entire file will be downloaded.
@@ -635,7 +1130,7 @@ impl Client {
             state.check_capability(B2Capability::READ_FILES)?;
 
             let resp = b2
-                .req(Method::GET, &state.auth, {
+                .transfer_req(Method::GET, &state.auth, {
                     state.url(match file {
                         DownloadFileBy::FileId(_) => "b2_download_file_by_id",
                         DownloadFileBy::FileName(_) => "b2_download_file_by_name",
@@ -788,6 +1283,162 @@ impl Client {
         .await
     }
 
+    /// Copies an existing file, or a range of it, to a new file on the server side using the `b2_copy_file` API.
+    ///
+    /// The source and destination must be in the same account. Files larger than 5GB must be copied
+    /// in parts with [`LargeFileUpload::copy_part`].
+    ///
+    /// File retention and legal hold settings are not copied from the source file,
+    /// and must be provided in [`CopyFile::retention`] and [`CopyFile::legal_hold`] if desired.
+    pub async fn copy_file(&self, copy: &CopyFile<'_>) -> Result<models::B2FileInfo, B2Error> {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct B2CopyFile<'a> {
+            source_file_id: &'a str,
+
+            #[serde(skip_serializing_if = "Option::is_none")]
+            destination_bucket_id: Option<&'a str>,
+
+            file_name: &'a str,
+
+            #[serde(skip_serializing_if = "Option::is_none")]
+            range: Option<String>,
+
+            metadata_directive: &'static str,
+
+            #[serde(skip_serializing_if = "Option::is_none")]
+            content_type: Option<&'a str>,
+
+            #[serde(skip_serializing_if = "Option::is_none")]
+            file_info: Option<&'a std::collections::HashMap<SmolStr, SmolStr>>,
+
+            #[serde(skip_serializing_if = "Option::is_none")]
+            file_retention: Option<&'a FileRetention>,
+
+            #[serde(skip_serializing_if = "Option::is_none")]
+            legal_hold: Option<&'a str>,
+
+            #[serde(skip_serializing_if = "Option::is_none")]
+            source_server_side_encryption: Option<sse::ServerSideEncryption>,
+
+            #[serde(skip_serializing_if = "sse::ServerSideEncryption::is_default")]
+            destination_server_side_encryption: &'a sse::ServerSideEncryption,
+        }
+
+        let (metadata_directive, content_type, file_info) = match copy.metadata {
+            MetadataDirective::Copy => ("COPY", None, None),
+            MetadataDirective::Replace {
+                content_type,
+                file_info,
+            } => ("REPLACE", Some(content_type), file_info),
+        };
+
+        let body = &B2CopyFile {
+            source_file_id: copy.source_file_id,
+            destination_bucket_id: copy.destination_bucket_id,
+            file_name: copy.file_name,
+            range: types::copy_range(&copy.range),
+            metadata_directive,
+            content_type,
+            file_info,
+            file_retention: copy.retention.as_ref(),
+            legal_hold: copy.legal_hold.map(|lh| if lh { "on" } else { "off" }),
+            source_server_side_encryption: copy.source_encryption.clone().map(sse::ServerSideEncryption::Customer),
+            destination_server_side_encryption: &copy.encryption,
+        };
+
+        self.run_request_with_reauth(|b2| async move {
+            let state = b2.state.read().await;
+
+            state.check_capability(B2Capability::READ_FILES | B2Capability::WRITE_FILES)?;
+            state.check_prefix(Some(body.file_name))?;
+
+            Self::json(b2.req(Method::POST, &state.auth, state.url("b2_copy_file")).json(body)).await
+        })
+        .await
+    }
+
+    /// Moves a file version to a new name in the same bucket, as B2 has no rename.
+    ///
+    /// The file is copied on the server side, preserving its content type, file info, file retention
+    /// and legal hold settings. Once the copy is verified to have the same length and SHA1 hash, the source
+    /// version is deleted. For large files, the SHA1 hash is taken from their `large_file_sha1` file info,
+    /// and files without any known SHA1 hash fail with [`B2Error::MissingSha1`] before being copied.
+    /// A copy that fails verification is deleted.
+    ///
+    /// `encryption` must be provided if the file is encrypted with SSE-C,
+    /// and the new file will be encrypted with the same key.
+    ///
+    /// If any step fails, [`B2Error::MoveFile`] is returned with the step that failed,
+    /// and the copy if it was created, so a partial move is never silent.
+    pub async fn move_file(
+        &self,
+        file: &models::B2FileInfo,
+        new_name: &str,
+        encryption: Option<sse::ServerSideEncryptionCustomer>,
+    ) -> Result<models::B2FileInfo, B2Error> {
+        use error::{MoveFileError, MoveFileStep};
+
+        let fail = |step, copy, error| {
+            B2Error::MoveFile(Box::new(MoveFileError {
+                step,
+                file_name: file.file_name.clone(),
+                file_id: file.file_id.clone(),
+                new_name: new_name.into(),
+                copy,
+                error,
+            }))
+        };
+
+        let Some(expected_sha1) = file.known_sha1() else {
+            return Err(fail(MoveFileStep::Copy, None, B2Error::MissingSha1));
+        };
+
+        let copy = match self
+            .copy_whole_file(
+                file,
+                Some(&file.bucket_id),
+                new_name,
+                encryption.clone(),
+                &encryption.into(),
+            )
+            .await
+        {
+            Ok(copy) => copy,
+            Err(e) => return Err(fail(MoveFileStep::Copy, None, e)),
+        };
+
+        let mismatch = if copy.content_length != file.content_length {
+            Some(B2Error::ContentLengthMismatch {
+                expected: file.content_length,
+                actual: copy.content_length,
+            })
+        } else {
+            match copy.known_sha1() {
+                Some(actual) if actual.eq_ignore_ascii_case(expected_sha1) => None,
+                actual => Some(B2Error::Sha1Mismatch {
+                    expected: expected_sha1.to_owned(),
+                    actual: actual.unwrap_or("none").to_owned(),
+                }),
+            }
+        };
+
+        if let Some(e) = mismatch {
+            // the copy cannot be trusted, so only keep it if it could not be deleted
+            let copy = match self.delete_file(&copy.file_id, &copy.file_name, false).await {
+                Ok(()) => None,
+                Err(_) => Some(copy),
+            };
+
+            return Err(fail(MoveFileStep::Verify, copy, e));
+        }
+
+        match self.delete_file(&file.file_id, &file.file_name, false).await {
+            Ok(()) => Ok(copy),
+            Err(e) => Err(fail(MoveFileStep::DeleteSource, Some(copy), e)),
+        }
+    }
+
     /// Modifies the Object Lock legal hold status for an existing file.
     ///
     /// Used to enable legal hold for a file in an Object Lock-enabled bucket,
@@ -978,6 +1629,9 @@ impl Client {
 
             #[serde(skip_serializing_if = "sse::ServerSideEncryption::is_default")]
             encryption: &'a sse::ServerSideEncryption,
+
+            #[serde(skip_serializing_if = "Option::is_none")]
+            file_info: Option<&'a std::collections::HashMap<SmolStr, SmolStr>>,
         }
 
         let info = self
@@ -994,6 +1648,7 @@ impl Client {
                     file_retention: info.retention.as_ref(),
                     legal_hold: info.legal_hold.map(|lh| if lh { "on" } else { "off" }),
                     encryption: &info.encryption,
+                    file_info: info.file_info,
                 };
 
                 Client::json::<models::B2FileInfo>(
@@ -1058,13 +1713,36 @@ impl RawUploadUrl {
     /// Actually performs the upload, with automatic reauthorization if necessary.
     async fn do_upload<F, T>(&mut self, f: F) -> Result<T, B2Error>
     where
-        F: Fn(reqwest::RequestBuilder) -> reqwest::RequestBuilder,
+        F: Fn(transport::RequestBuilder) -> transport::RequestBuilder,
         T: serde::de::DeserializeOwned,
     {
+        #[cfg(feature = "tracing")]
+        let span = trace::op_span();
+
+        #[cfg(feature = "tracing")]
+        let mut attempt: u64 = 0;
+
         loop {
-            let res = Client::json(f(self.client.req(Method::POST, &self.auth, &self.url.upload_url)));
+            let req = f(self.client.transfer_req(Method::POST, &self.auth, &self.url.upload_url));
+
+            let res = async {
+                match self.client.timeouts.stall {
+                    Some(stall) => stall::watch(req, stall, Client::json).await,
+                    None => Client::json(req).await,
+                }
+            };
+
+            #[cfg(feature = "tracing")]
+            let res = {
+                attempt += 1;
+                tracing::Instrument::instrument(res, span.record("attempt", attempt).clone())
+            };
+
             return match res.await {
                 Err(B2Error::B2ErrorMessage(e)) if e.status == 401 => {
+                    #[cfg(feature = "tracing")]
+                    tracing::info!(parent: &span, "refreshing upload URL after 401 Unauthorized");
+
                     let get_new_url =
                         self.client.get_b2_upload_url(self.url.bucket_id.as_deref(), self.url.file_id.as_deref());
 
@@ -1074,6 +1752,11 @@ impl RawUploadUrl {
                     self.url = url;
                     self.prefix = prefix;
 
+                    if let Some(ref metrics) = self.client.metrics {
+                        metrics.upload_url_refreshed();
+                        metrics.retry();
+                    }
+
                     continue;
                 }
                 res => res,
@@ -1095,14 +1778,11 @@ impl RawUploadUrl {
     {
         self.check_prefix(info.file_name)?;
 
-        self.do_upload(|builder| {
-            builder.body(file.make()).headers({
-                let mut headers = HeaderMap::new();
-                info.add_headers(&mut headers);
-                headers
-            })
-        })
-        .await
+        let mut headers = HeaderMap::new();
+        info.add_headers(&mut headers)?;
+
+        self.do_upload(|builder| builder.body(make_body(&info.content_sha1, &file)).headers(headers.clone()))
+            .await
     }
 
     async fn upload_part<F, B>(&mut self, info: &NewPartInfo<'_>, body: F) -> Result<models::B2PartInfo, B2Error>
@@ -1111,7 +1791,7 @@ impl RawUploadUrl {
         B: Into<reqwest::Body>,
     {
         self.do_upload(|builder| {
-            builder.body(body.make()).headers({
+            builder.body(make_body(&info.content_sha1, &body)).headers({
                 let mut headers = HeaderMap::new();
                 info.add_headers(&mut headers);
                 headers
@@ -1121,6 +1801,49 @@ impl RawUploadUrl {
     }
 }
 
+/// Creates the request body, appending the SHA1 hash computed while streaming if requested.
+fn make_body<F, B>(content_sha1: &ContentSha1<'_>, body: &F) -> reqwest::Body
+where
+    F: MakeBody<B>,
+    B: Into<reqwest::Body>,
+{
+    match content_sha1 {
+        ContentSha1::Compute => checksum::Sha1Stream::append(body.make()),
+        _ => body.make().into(),
+    }
+}
+
+/// Runs an upload with the body wrapped in a [`checksum::Sha1Stream`], which aborts the request
+/// if the streamed content does not match the expected SHA1 hash.
+async fn upload_checked<F, B, R, U>(expected: Option<&str>, body: F, upload: U) -> Result<R, B2Error>
+where
+    F: MakeBody<B>,
+    B: Into<reqwest::Body>,
+    U: AsyncFnOnce(&dyn Fn() -> reqwest::Body) -> Result<R, B2Error>,
+{
+    let Some(expected) = expected else {
+        return Err(B2Error::MissingSha1);
+    };
+
+    let expected: Arc<str> = Arc::from(expected);
+    let slot = checksum::Sha1Slot::default();
+
+    let res = upload(&|| checksum::Sha1Stream::verify(body.make(), expected.clone(), slot.clone())).await;
+
+    if res.is_err() {
+        if let Some(actual) = slot.lock().unwrap_or_else(|e| e.into_inner()).take() {
+            if !actual.eq_ignore_ascii_case(&expected) {
+                return Err(B2Error::Sha1Mismatch {
+                    expected: expected.to_string(),
+                    actual,
+                });
+            }
+        }
+    }
+
+    res
+}
+
 impl UploadUrl {
     /// Uploads a file to the B2 API using the URL acquired from [`Client::get_upload_url`].
     ///
@@ -1139,16 +1862,56 @@ impl UploadUrl {
         self.0.upload_file(info, file).await
     }
 
+    /// Uploads a file to the B2 API using the URL acquired from [`Client::get_upload_url`],
+    /// computing the SHA1 hash of the body while it is streamed.
+    ///
+    /// If the streamed body does not match [`NewFileInfo::content_sha1`], the request is aborted
+    /// before B2 can accept the file, and [`B2Error::Sha1Mismatch`] is returned.
+    ///
+    /// `content_sha1` must be [`ContentSha1::Hex`] to use this method.
+    pub async fn upload_file_checked<F, B>(
+        &mut self,
+        info: &NewFileInfo<'_>,
+        file: F,
+    ) -> Result<models::B2FileInfo, B2Error>
+    where
+        F: MakeBody<B>,
+        B: Into<reqwest::Body>,
+    {
+        upload_checked(info.content_sha1.hex(), file, async |body| {
+            self.0.upload_file(info, body).await
+        })
+        .await
+    }
+
     /// Uploads a file to the B2 API using the URL acquired from [`Client::get_upload_url`].
     ///
     /// The `bytes` parameter is a value to be converted into the body of the request.
+    ///
+    /// If [`NewFileInfo::content_sha1`] is [`ContentSha1::Compute`], the SHA1 hash
+    /// will be computed from `bytes` before uploading, otherwise the provided hash is not checked.
+    /// This avoids the `hex_digits_at_end` mode used for streamed bodies.
     pub async fn upload_file_bytes(
         &mut self,
         info: &NewFileInfo<'_>,
         bytes: impl Into<bytes::Bytes>,
     ) -> Result<models::B2FileInfo, B2Error> {
         let bytes = bytes.into();
-        self.upload_file(info, || bytes.clone()).await
+
+        if info.content_sha1.hex().is_some() {
+            return self.upload_file(info, || bytes.clone()).await;
+        }
+
+        let sha1 = checksum::sha1_hex(&bytes);
+
+        let info = NewFileInfo {
+            content_sha1: ContentSha1::Hex(&sha1),
+            encryption: info.encryption.clone(),
+            retention: info.retention.clone(),
+            ..*info
+        };
+
+        self.upload_file(&info, || bytes.clone()).await
     }
 }
 
@@ -1167,6 +1930,17 @@ impl LargeFileUpload {
         &self.info
     }
 
+    /// Continues an existing unfinished large file with the given file ID.
+    pub(crate) fn existing(client: &Client, file_id: SmolStr) -> LargeFileUpload {
+        LargeFileUpload {
+            client: client.clone(),
+            info: models::B2FileInfo {
+                file_id,
+                ..Default::default()
+            },
+        }
+    }
+
     /// Equivalent to [`Client::start_large_file`].
     pub async fn start(
         client: &Client,
@@ -1195,7 +1969,8 @@ impl LargeFileUpload {
     /// may need to be called multiple times if the request needs to be retried. Therefore, it is recommended
     /// the body-creation closure be cheap to call multiple times.
     ///
-    /// **NOTE**: This method does not check if the provided SHA1 hash is correct.
+    /// **NOTE**: This method does not check if the provided SHA1 hash is correct,
+    /// see [`LargeFileUpload::upload_part_checked`] for that.
     pub async fn upload_part<F, B>(
         &self,
         url: &mut UploadPartUrl,
@@ -1223,7 +1998,9 @@ impl LargeFileUpload {
     ///
     /// The `bytes` parameter is a value to be converted into the body of the request.
     ///
-    /// **NOTE**: This method does not check if the provided SHA1 hash is correct.
+    /// If [`NewPartInfo::content_sha1`] is [`ContentSha1::Compute`], the SHA1 hash
+    /// will be computed from `bytes` before uploading, otherwise the provided hash is not checked.
+    /// This avoids the `hex_digits_at_end` mode used for streamed bodies.
     pub async fn upload_part_bytes(
         &self,
         url: &mut UploadPartUrl,
@@ -1231,7 +2008,88 @@ impl LargeFileUpload {
         bytes: impl Into<bytes::Bytes>,
     ) -> Result<models::B2PartInfo, B2Error> {
         let bytes = bytes.into();
-        self.upload_part(url, info, || bytes.clone()).await
+
+        if info.content_sha1.hex().is_some() {
+            return self.upload_part(url, info, || bytes.clone()).await;
+        }
+
+        let sha1 = checksum::sha1_hex(&bytes);
+
+        let info = NewPartInfo {
+            content_sha1: ContentSha1::Hex(&sha1),
+            encryption: info.encryption.clone(),
+            ..*info
+        };
+
+        self.upload_part(url, &info, || bytes.clone()).await
+    }
+
+    /// Uploads a part of a large file to the given upload URL, computing the SHA1 hash
+    /// of the body while it is streamed.
+    ///
+    /// If the streamed body does not match [`NewPartInfo::content_sha1`], the request is aborted
+    /// before B2 can accept the part, and [`B2Error::Sha1Mismatch`] is returned.
+    ///
+    /// `content_sha1` must be [`ContentSha1::Hex`] to use this method.
+    ///
+    /// See [`LargeFileUpload::upload_part`] for more information.
+    pub async fn upload_part_checked<F, B>(
+        &self,
+        url: &mut UploadPartUrl,
+        info: &NewPartInfo<'_>,
+        part: F,
+    ) -> Result<models::B2PartInfo, B2Error>
+    where
+        F: MakeBody<B>,
+        B: Into<reqwest::Body>,
+    {
+        upload_checked(info.content_sha1.hex(), part, async |body| {
+            self.upload_part(url, info, body).await
+        })
+        .await
+    }
+
+    /// Copies a range of an existing file as a part of this large file on the server side,
+    /// using the `b2_copy_part` API. Once all parts have been uploaded or copied,
+    /// call [`LargeFileUpload::finish`] to complete the upload.
+    ///
+    /// The source file must be in the same account as the large file.
+    pub async fn copy_part(&self, copy: &CopyPart<'_>) -> Result<models::B2PartInfo, B2Error> {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct B2CopyPart<'a> {
+            source_file_id: &'a str,
+            large_file_id: &'a str,
+            part_number: std::num::NonZeroU32,
+
+            #[serde(skip_serializing_if = "Option::is_none")]
+            range: Option<String>,
+
+            #[serde(skip_serializing_if = "Option::is_none")]
+            source_server_side_encryption: Option<sse::ServerSideEncryption>,
+
+            #[serde(skip_serializing_if = "sse::ServerSideEncryption::is_default")]
+            destination_server_side_encryption: &'a sse::ServerSideEncryption,
+        }
+
+        let body = &B2CopyPart {
+            source_file_id: copy.source_file_id,
+            large_file_id: &self.info.file_id,
+            part_number: copy.part_number,
+            range: types::copy_range(&copy.range),
+            source_server_side_encryption: copy.source_encryption.clone().map(sse::ServerSideEncryption::Customer),
+            destination_server_side_encryption: &copy.encryption,
+        };
+
+        self.client
+            .run_request_with_reauth(|b2| async move {
+                let state = b2.state.read().await;
+
+                state.check_capability(B2Capability::READ_FILES | B2Capability::WRITE_FILES)?;
+
+                Client::json(b2.req(Method::POST, &state.auth, state.url("b2_copy_part")).json(body)).await
+            })
+            .await
     }
 
     /// Converts the parts that have been uploaded into a single B2 file.
@@ -1302,6 +2160,133 @@ mod tests {
 
     use super::*;
 
+    /// Builders for replayed sessions, shared by the offline tests.
+    mod fixture {
+        use std::sync::Arc;
+
+        use serde_json::{json, Value};
+
+        use crate::replay::{Interaction, ReplayTransport};
+        use crate::{models, B2Capability, Client, ClientBuilder};
+
+        pub const API_URL: &str = "https://api.example.com";
+
+        /// An interaction responding to any request to `method url`.
+        pub fn interaction(method: &str, url: &str, status: u16, headers: Value, body: Value) -> Interaction {
+            serde_json::from_value(json!({
+                "request": { "method": method, "url": url },
+                "response": { "status": status, "headers": headers, "body": body },
+            }))
+            .unwrap()
+        }
+
+        /// A successful call of an API operation, with any request body.
+        pub fn api(op: &str, resp: Value) -> Interaction {
+            api_status(op, 200, resp)
+        }
+
+        /// A successful `GET` of an API operation, such as `b2_list_file_names?bucketId=bucket_id`.
+        pub fn get(op: &str, resp: Value) -> Interaction {
+            let headers = json!({ "content-type": "application/json;charset=utf-8" });
+
+            interaction("GET", &url(op), 200, headers, json!({ "json": resp }))
+        }
+
+        /// A call of an API operation responding with the given status.
+        pub fn api_status(op: &str, status: u16, resp: Value) -> Interaction {
+            let headers = json!({ "content-type": "application/json;charset=utf-8" });
+
+            interaction("POST", &url(op), status, headers, json!({ "json": resp }))
+        }
+
+        /// A call of an API operation only matching the given request body.
+        pub fn api_with(op: &str, req: Value, resp: Value) -> Interaction {
+            let mut interaction = api(op, resp);
+            interaction.request.body = Some(crate::replay::RecordedBody::Json(req));
+            interaction
+        }
+
+        /// A B2 error response, such as `401, "expired_auth_token"`.
+        pub fn api_error(op: &str, status: u16, code: &str) -> Interaction {
+            api_status(op, status, json!({ "status": status, "code": code, "message": code }))
+        }
+
+        /// The URL of an API operation.
+        pub fn url(op: &str) -> String {
+            format!("{API_URL}/b2api/v3/{op}")
+        }
+
+        /// A download of a file with the given contents.
+        pub fn download(query: &str, file_name: &str, contents: &str) -> Interaction {
+            let headers = json!({
+                "content-type": "text/plain",
+                "content-length": contents.len().to_string(),
+                "x-bz-file-id": "file_id",
+                "x-bz-file-name": file_name,
+                "x-bz-content-sha1": crate::checksum::sha1_hex(contents.as_bytes()),
+                "x-bz-upload-timestamp": "0",
+            });
+
+            let url = format!("{}?{query}", url("b2_download_file_by_id"));
+
+            interaction("GET", &url, 200, headers, json!({ "text": contents }))
+        }
+
+        /// The JSON of a file version.
+        pub fn file(file_id: &str, file_name: &str) -> Value {
+            json!({ "fileId": file_id, "fileName": file_name, "bucketId": "bucket_id", "action": "upload" })
+        }
+
+        /// `b2_authorize_account` for an unrestricted key, with any fields of `storageApi` replaced by `storage`.
+        pub fn authorize(storage: Value) -> Interaction {
+            let mut api = json!({
+                "apiUrl": API_URL,
+                "downloadUrl": "https://f000.example.com",
+                "recommendedPartSize": 100000000,
+                "absoluteMinimumPartSize": 5000000,
+                "s3ApiUrl": "https://s3.example.com",
+                "capabilities": models::capabilities::B2CapabilitiesStringSet::from(B2Capability::all()),
+            });
+
+            if let (Some(api), Value::Object(storage)) = (api.as_object_mut(), storage) {
+                api.extend(storage);
+            }
+
+            let body =
+                json!({ "accountId": "account", "authorizationToken": "token", "apiInfo": { "storageApi": api } });
+
+            interaction(
+                "GET",
+                "https://api.backblazeb2.com/b2api/v3/b2_authorize_account",
+                200,
+                json!({ "content-type": "application/json;charset=utf-8" }),
+                json!({ "json": body }),
+            )
+        }
+
+        /// Authorizes a client replaying `interactions`, after authorizing with the given `storageApi` fields.
+        pub async fn client(storage: Value, interactions: Vec<Interaction>) -> (Client, Arc<ReplayTransport>) {
+            let builder = ClientBuilder::new("key_id", "key");
+
+            client_with(builder, storage, interactions).await
+        }
+
+        /// As [`client`], with a configured builder.
+        pub async fn client_with(
+            builder: ClientBuilder,
+            storage: Value,
+            interactions: Vec<Interaction>,
+        ) -> (Client, Arc<ReplayTransport>) {
+            let mut all = vec![authorize(storage)];
+            all.extend(interactions);
+
+            let replay = Arc::new(ReplayTransport::new(all));
+            let client = builder.transport(replay.clone()).authorize().await.unwrap();
+
+            (client, replay)
+        }
+    }
+
     #[test]
     fn test_downloadby_serialization() {
         let file_id = "4_zc1234567890abcdef1234f1";
@@ -1314,10 +2299,1482 @@ mod tests {
         assert_eq!(file_name_json, format!(r#"{{"fileName":"{}"}}"#, file_name));
     }
 
+    #[test]
+    fn test_sse_c_serialization() {
+        let json = serde_json::to_value(sse::ServerSideEncryption::customer_aes256(&[0; 32])).unwrap();
+
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "mode": "SSE-C",
+                "algorithm": "AES256",
+                "customerKey": "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=",
+                "customerKeyMd5": "cLyPS3KoaSFGi/joRB3OUQ==",
+            })
+        );
+    }
+
+    #[test]
+    fn test_file_info_headers() {
+        use std::collections::HashMap;
+
+        let header = |file_info: &HashMap<SmolStr, SmolStr>| {
+            let info = NewFileInfo::builder().file_name("file").content_length(1).file_info(file_info).build();
+
+            let mut headers = HeaderMap::new();
+            info.add_headers(&mut headers).map(|_| headers)
+        };
+
+        let headers = header(&HashMap::from([("Author".into(), "Jane Doe/ü".into())])).unwrap();
+        assert_eq!(headers["x-bz-info-author"], "Jane%20Doe/%C3%BC");
+
+        let err = header(&HashMap::from([("bad key".into(), "value".into())])).unwrap_err();
+        assert!(matches!(err, B2Error::InvalidFileInfoKey(key) if key == "bad key"));
+
+        let too_many = (0..11).map(|i| (SmolStr::from(format!("key{i}")), SmolStr::from("value"))).collect();
+        assert!(matches!(header(&too_many), Err(B2Error::TooManyFileInfo(11))));
+    }
+
+    #[cfg(feature = "fs")]
     #[tokio::test]
-    async fn test_auth() {
-        use sha1::{Digest, Sha1};
+    async fn test_upload_from_path_file_info_limit() {
+        let path = std::env::temp_dir().join(format!("yab2-file-info-{}", std::process::id()));
+        std::fs::write(&path, b"test").unwrap();
+
+        // no room is left for `src_last_modified_millis`, so nothing is sent
+        let file_info: std::collections::HashMap<_, _> =
+            (0..10).map(|i| (SmolStr::from(format!("key{i}")), SmolStr::from("value"))).collect();
+        let info = fs::NewFileFromPath::builder().path(&path).file_info(&file_info).build();
+
+        let (client, replay) = fixture::client(serde_json::json!({}), vec![]).await;
+
+        let res = client.upload_from_path(&info, Some("bucket_id"), None).await;
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(res, Err(B2Error::TooManyFileInfo(11))));
+        replay.finish().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_sha1_stream() {
+        use futures_util::TryStreamExt;
+        use http_body_util::BodyDataStream;
+
+        let slot = checksum::Sha1Slot::default();
+        let expected: Arc<str> = Arc::from(checksum::sha1_hex(b"hello world"));
+
+        let body = checksum::Sha1Stream::verify("hello world", expected.clone(), slot.clone());
+        let chunks: Vec<_> = BodyDataStream::new(body).try_collect().await.unwrap();
+
+        assert_eq!(chunks.concat(), b"hello world");
+        assert_eq!(slot.lock().unwrap().as_deref(), Some(&*expected));
+
+        let body = checksum::Sha1Stream::verify("hello there", expected.clone(), slot.clone());
+        assert!(BodyDataStream::new(body).try_collect::<Vec<_>>().await.is_err());
+        assert_ne!(slot.lock().unwrap().as_deref(), Some(&*expected));
 
+        let body = checksum::Sha1Stream::append("hello world");
+        let chunks: Vec<_> = BodyDataStream::new(body).try_collect().await.unwrap();
+
+        assert_eq!(chunks.concat(), format!("hello world{expected}").as_bytes());
+    }
+
+    #[cfg(feature = "fs")]
+    #[tokio::test]
+    async fn test_walk_dir() {
+        let root = std::env::temp_dir().join(format!("yab2-walk-dir-{}", std::process::id()));
+
+        for path in ["a.txt", "b.log", "sub/c.txt", "sub/deeper/d.txt", "skip/e.txt"] {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, b"test").unwrap();
+        }
+
+        let (files, failed) =
+            fs::walk_dir(&root, &fs::PathFilter::new(&["**/*.txt"], &["skip/**"]).unwrap()).await.unwrap();
+
+        std::fs::remove_dir_all(&root).unwrap();
+
+        let mut names: Vec<_> = files.iter().map(|file| file.relative.as_str()).collect();
+        names.sort_unstable();
+
+        assert!(failed.is_empty());
+        assert_eq!(names, ["a.txt", "sub/c.txt", "sub/deeper/d.txt"]);
+    }
+
+    #[cfg(feature = "fs")]
+    #[tokio::test]
+    async fn test_upload_from_path_progress() {
+        use serde_json::json;
+
+        let path = std::env::temp_dir().join(format!("yab2-progress-{}", std::process::id()));
+        std::fs::write(&path, vec![1; 200_000]).unwrap();
+
+        let upload_url = "https://pod.example.com/b2api/v3/b2_upload_file/bucket_id/c001";
+
+        let (client, replay) = fixture::client(
+            json!({}),
+            vec![
+                fixture::get(
+                    "b2_get_upload_url?bucketId=bucket_id",
+                    json!({ "bucketId": "bucket_id", "uploadUrl": upload_url, "authorizationToken": "token" }),
+                ),
+                fixture::interaction(
+                    "POST",
+                    upload_url,
+                    200,
+                    json!({ "content-type": "application/json" }),
+                    json!({ "json": fixture::file("file_id", "progress.bin") }),
+                ),
+            ],
+        )
+        .await;
+
+        let updates = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+        let info = fs::NewFileFromPath::builder()
+            .path(&path)
+            .file_name("progress.bin")
+            .progress({
+                let updates = updates.clone();
+                move |progress| updates.lock().unwrap().push(progress)
+            })
+            .build();
+
+        let res = client.upload_from_path(&info, Some("bucket_id"), None).await;
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(res.unwrap().file_id, "file_id");
+        replay.finish().unwrap();
+
+        let updates = updates.lock().unwrap();
+
+        let hashed: Vec<_> = updates.iter().filter_map(|p| p.part).filter(|p| !p.completed).collect();
+        assert!(hashed.len() > 1);
+        assert!(hashed.iter().all(|p| p.part_number == 1 && p.content_length == 200_000));
+        assert!(hashed.windows(2).all(|w| w[0].bytes_hashed < w[1].bytes_hashed));
+
+        assert_eq!(
+            *updates.last().unwrap(),
+            UploadProgress {
+                total_bytes: 200_000,
+                bytes_hashed: 200_000,
+                bytes_sent: 200_000,
+                parts_completed: 1,
+                num_parts: 1,
+                retries: 0,
+                part: Some(PartProgress {
+                    part_number: 1,
+                    content_length: 200_000,
+                    bytes_hashed: 200_000,
+                    completed: true,
+                }),
+            }
+        );
+    }
+
+    #[cfg(feature = "fs")]
+    #[tokio::test]
+    async fn test_upload_from_path_cancel() {
+        use serde_json::json;
+
+        let path = std::env::temp_dir().join(format!("yab2-cancel-{}", std::process::id()));
+        std::fs::write(&path, vec![1; 20_000]).unwrap();
+
+        let cancel = tokio_util::sync::CancellationToken::new();
+        cancel.cancel();
+
+        let info = fs::NewFileFromPath::builder().path(&path).file_name("cancel.bin").cancel(cancel).build();
+
+        // small files are cancelled before any request is made
+        let (client, replay) = fixture::client(json!({}), vec![]).await;
+
+        let res = client.upload_from_path(&info, Some("bucket_id"), None).await;
+        assert!(matches!(res, Err(B2Error::Cancelled)));
+        replay.finish().unwrap();
+
+        // large files are cancelled on B2 so no unfinished large file is left behind
+        let cancelled = json!({
+            "fileId": "large_id",
+            "fileName": "cancel.bin",
+            "bucketId": "bucket_id",
+            "accountId": "account",
+        });
+
+        let (client, replay) = fixture::client(
+            json!({ "recommendedPartSize": 10_000 }),
+            vec![
+                fixture::api("b2_start_large_file", fixture::file("large_id", "cancel.bin")),
+                fixture::api("b2_cancel_large_file", cancelled),
+            ],
+        )
+        .await;
+
+        let res = client.upload_from_path(&info, Some("bucket_id"), None).await;
+
+        assert!(matches!(res, Err(B2Error::Cancelled)));
+        replay.finish().unwrap();
+
+        // resumable large files are only paused
+        let info = fs::NewFileFromPath {
+            resumable: true,
+            ..info
+        };
+
+        let (client, replay) = fixture::client(
+            json!({ "recommendedPartSize": 10_000 }),
+            vec![fixture::api(
+                "b2_start_large_file",
+                fixture::file("large_id", "cancel.bin"),
+            )],
+        )
+        .await;
+
+        let res = client.upload_from_path(&info, Some("bucket_id"), None).await;
+        std::fs::remove_file(&path).unwrap();
+
+        match res {
+            Err(B2Error::IncompleteLargeFile(incomplete)) => {
+                assert_eq!(incomplete.file_id, "large_id");
+                assert!(matches!(incomplete.error, B2Error::Cancelled));
+            }
+            res => panic!("expected an incomplete large file, got {res:?}"),
+        }
+
+        replay.finish().unwrap();
+    }
+
+    #[cfg(feature = "fs")]
+    #[tokio::test]
+    async fn test_upload_from_path_resume() {
+        use serde_json::json;
+
+        let path = std::env::temp_dir().join(format!("yab2-resume-{}", std::process::id()));
+        std::fs::write(&path, vec![1; 20_000]).unwrap();
+
+        let part = |part_number: u64, sha1: &str| {
+            json!({
+                "fileId": "large_id",
+                "partNumber": part_number,
+                "contentLength": 10_000,
+                "contentSha1": sha1,
+                "uploadTimestamp": 0,
+            })
+        };
+
+        // part 3 is left over from when the file was longer
+        let resume = error::IncompleteLargeFile {
+            file_id: "large_id".into(),
+            part_size: 10_000,
+            parts: vec![
+                serde_json::from_value(part(3, "sha1_3")).unwrap(),
+                serde_json::from_value(part(2, "sha1_2")).unwrap(),
+            ],
+            error: B2Error::Cancelled,
+        };
+
+        let upload_url = "https://pod.example.com/b2api/v3/b2_upload_part/large_id/c001";
+
+        let (client, replay) = fixture::client(
+            json!({ "recommendedPartSize": 10_000 }),
+            vec![
+                fixture::get(
+                    "b2_get_upload_part_url?fileId=large_id",
+                    json!({ "fileId": "large_id", "uploadUrl": upload_url, "authorizationToken": "token" }),
+                ),
+                fixture::interaction(
+                    "POST",
+                    upload_url,
+                    200,
+                    json!({ "content-type": "application/json" }),
+                    json!({ "json": part(1, "sha1_1") }),
+                ),
+                fixture::api_with(
+                    "b2_finish_large_file",
+                    json!({ "fileId": "large_id", "partSha1Array": ["sha1_1", "sha1_2"] }),
+                    fixture::file("large_id", "resume.bin"),
+                ),
+            ],
+        )
+        .await;
+
+        let info = fs::NewFileFromPath::builder()
+            .path(&path)
+            .file_name("resume.bin")
+            .max_simultaneous_uploads(1)
+            .resume(&resume)
+            .build();
+
+        let res = client.upload_from_path(&info, Some("bucket_id"), None).await;
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(res.unwrap().file_id, "large_id");
+        replay.finish().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_get_file_info() {
+        let (client, replay) = fixture::client(
+            serde_json::json!({}),
+            vec![fixture::get(
+                "b2_get_file_info?fileId=file_id",
+                fixture::file("file_id", "file.txt"),
+            )],
+        )
+        .await;
+
+        let file = client.get_file_info("file_id").await.unwrap();
+        replay.finish().unwrap();
+
+        assert_eq!(file.file_name, "file.txt");
+    }
+
+    #[tokio::test]
+    async fn test_move_file() {
+        use serde_json::json;
+
+        let sha1 = checksum::sha1_hex(b"contents");
+
+        let version = |id: &str, name: &str, sha1: &str| json!({ "fileId": id, "fileName": name, "bucketId": "bucket_id", "contentLength": 8, "contentSha1": sha1 });
+
+        let delete = |id: &str, name: &str| {
+            fixture::api_with(
+                "b2_delete_file_version",
+                json!({ "fileId": id, "fileName": name, "bypassGovernance": false }),
+                json!({ "fileId": id, "fileName": name }),
+            )
+        };
+
+        let source: models::B2FileInfo = serde_json::from_value(version("old_id", "old.txt", &sha1)).unwrap();
+
+        let (client, replay) = fixture::client(
+            json!({}),
+            vec![
+                // the first copy is corrupt, so it is deleted and the source is kept
+                fixture::api(
+                    "b2_copy_file",
+                    version("bad_id", "new.txt", &checksum::sha1_hex(b"other")),
+                ),
+                delete("bad_id", "new.txt"),
+                fixture::api("b2_copy_file", version("new_id", "new.txt", &sha1)),
+                delete("old_id", "old.txt"),
+            ],
+        )
+        .await;
+
+        match client.move_file(&source, "new.txt", None).await {
+            Err(B2Error::MoveFile(e)) => {
+                assert_eq!(e.step, error::MoveFileStep::Verify);
+                assert!(e.copy.is_none());
+                assert!(matches!(e.error, B2Error::Sha1Mismatch { .. }));
+            }
+            res => panic!("expected a failed move, got {res:?}"),
+        }
+
+        let moved = client.move_file(&source, "new.txt", None).await.unwrap();
+        assert_eq!(moved.file_id, "new_id");
+
+        replay.finish().unwrap();
+
+        // without a known SHA1 the copy cannot be verified, so nothing is copied
+        let unknown: models::B2FileInfo = serde_json::from_value(version("old_id", "old.txt", "none")).unwrap();
+
+        match client.move_file(&unknown, "new.txt", None).await {
+            Err(B2Error::MoveFile(e)) => {
+                assert_eq!(e.step, error::MoveFileStep::Copy);
+                assert!(matches!(e.error, B2Error::MissingSha1));
+            }
+            res => panic!("expected a failed move, got {res:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delete_prefix() {
+        use serde_json::json;
+
+        let version = |id: &str, action: &str, length: u64, legal_hold: Option<&str>| {
+            json!({
+                "fileId": id,
+                "fileName": format!("user/{id}.txt"),
+                "action": action,
+                "contentLength": length,
+                "legalHold": { "isClientAuthorizedToRead": true, "value": legal_hold },
+            })
+        };
+
+        let delete = |id: &str| {
+            fixture::api_with(
+                "b2_delete_file_version",
+                json!({ "fileId": id, "fileName": format!("user/{id}.txt"), "bypassGovernance": false }),
+                json!({ "fileId": id, "fileName": format!("user/{id}.txt") }),
+            )
+        };
+
+        let mut failed_delete = fixture::api_error("b2_delete_file_version", 400, "bad_request");
+        failed_delete.request.body = delete("e").request.body;
+
+        let (client, replay) = fixture::client(
+            json!({}),
+            vec![
+                fixture::get(
+                    "b2_list_file_versions?bucketId=bucket_id&maxFileCount=1000&prefix=user%2F",
+                    json!({
+                        "files": [
+                            version("a", "upload", 10, None),
+                            version("b", "hide", 0, None),
+                            version("c", "upload", 20, Some("on")),
+                        ],
+                        "nextFileName": "user/d.txt",
+                        "nextFileId": "d",
+                    }),
+                ),
+                fixture::get(
+                    "b2_list_file_versions?bucketId=bucket_id&startFileName=user%2Fd.txt&startFileId=d\
+                     &maxFileCount=1000&prefix=user%2F",
+                    json!({
+                        "files": [version("d", "start", 0, None), version("e", "upload", 5, Some("off"))],
+                        "nextFileName": null,
+                    }),
+                ),
+                delete("a"),
+                delete("b"),
+                delete("d"),
+                failed_delete,
+            ],
+        )
+        .await;
+
+        let report =
+            client.delete_prefix(Some("bucket_id"), "user/", &bulk::DeleteOptions::default()).await.unwrap();
+        replay.finish().unwrap();
+
+        assert_eq!(report.files_deleted, 1);
+        assert_eq!(report.bytes_deleted, 10);
+        assert_eq!(report.hide_markers_deleted, 1);
+        assert_eq!(report.large_files_cancelled, 1);
+        assert_eq!(report.legal_hold.iter().map(|v| &*v.file_id).collect::<Vec<_>>(), ["c"]);
+        assert_eq!(
+            report.failed.iter().map(|(v, _)| &*v.file_id).collect::<Vec<_>>(),
+            ["e"]
+        );
+        assert!(!report.is_success());
+    }
+
+    #[tokio::test]
+    async fn test_hide_prefix() {
+        use serde_json::json;
+
+        let version = |name: &str, action: &str| json!({ "fileId": name, "fileName": name, "action": action });
+
+        let hide = |name: &str| {
+            fixture::api_with(
+                "b2_hide_file",
+                json!({ "bucketId": "bucket_id", "fileName": name }),
+                json!({ "fileId": "hidden", "fileName": name, "action": "hide" }),
+            )
+        };
+
+        let (client, replay) = fixture::client(
+            json!({}),
+            vec![
+                fixture::get(
+                    "b2_list_file_names?bucketId=bucket_id&maxFileCount=1000&prefix=user%2F",
+                    json!({
+                        "files": [version("user/a.txt", "upload"), version("user/b.txt", "start")],
+                        "nextFileName": "user/c.txt",
+                    }),
+                ),
+                hide("user/a.txt"),
+                fixture::get(
+                    "b2_list_file_names?bucketId=bucket_id&startFileName=user%2Fc.txt\
+                     &maxFileCount=1000&prefix=user%2F",
+                    json!({ "files": [version("user/c.txt", "upload")], "nextFileName": null }),
+                ),
+                hide("user/c.txt"),
+            ],
+        )
+        .await;
+
+        let report = client.hide_prefix(Some("bucket_id"), "user/", 0).await.unwrap();
+        replay.finish().unwrap();
+
+        // unfinished large files are skipped
+        assert_eq!(report.hidden, 2);
+        assert!(report.is_success());
+    }
+
+    #[tokio::test]
+    async fn test_unhide_file() {
+        use serde_json::json;
+
+        let version = |id: &str, action: &str| json!({ "fileId": id, "fileName": "a.txt", "action": action });
+        let list = "b2_list_file_versions?bucketId=bucket_id&startFileName=a.txt&maxFileCount=1&prefix=a.txt";
+
+        let (client, replay) = fixture::client(
+            json!({}),
+            vec![
+                // only the latest version is listed, so older hide markers are kept
+                fixture::get(
+                    list,
+                    json!({ "files": [version("hide_2", "hide")], "nextFileName": "a.txt" }),
+                ),
+                fixture::api_with(
+                    "b2_delete_file_version",
+                    json!({ "fileId": "hide_2", "fileName": "a.txt", "bypassGovernance": false }),
+                    json!({ "fileId": "hide_2", "fileName": "a.txt" }),
+                ),
+                fixture::get(
+                    list,
+                    json!({ "files": [version("upload_1", "upload")], "nextFileName": "a.txt" }),
+                ),
+            ],
+        )
+        .await;
+
+        client.unhide_file(Some("bucket_id"), "a.txt").await.unwrap();
+
+        let err = client.unhide_file(Some("bucket_id"), "a.txt").await.unwrap_err();
+        assert!(
+            matches!(err, B2Error::NotHidden(ref name) if name == "a.txt"),
+            "{err:?}"
+        );
+
+        replay.finish().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_restore_version() {
+        use replay::{RecordedBody, RecordingTransport, ReplayTransport};
+        use serde_json::json;
+
+        let version = |id: &str, length: u64| {
+            let mut file = fixture::file(id, "a.txt");
+            file["contentLength"] = json!(length);
+            file
+        };
+
+        let part = |number: u64| {
+            json!({
+                "fileId": "large_id",
+                "partNumber": number,
+                "contentLength": 1,
+                "contentSha1": "none",
+                "uploadTimestamp": 0,
+            })
+        };
+
+        // versions over 5 GB are copied in parts
+        let large = 6_000_000_000;
+
+        let recorder = Arc::new(RecordingTransport::new(ReplayTransport::new(vec![
+            fixture::authorize(json!({ "recommendedPartSize": 5_000_000_000u64 })),
+            fixture::get("b2_get_file_info?fileId=old_id", version("old_id", 10)),
+            fixture::api("b2_copy_file", version("new_id", 10)),
+            fixture::get("b2_get_file_info?fileId=large_old_id", version("large_old_id", large)),
+            fixture::api("b2_start_large_file", version("large_id", 0)),
+            fixture::api("b2_copy_part", part(1)),
+            fixture::api("b2_copy_part", part(2)),
+            fixture::api("b2_finish_large_file", version("large_id", large)),
+        ])));
+
+        let client = ClientBuilder::new("key_id", "key").transport(recorder.clone()).authorize().await.unwrap();
+
+        assert_eq!(client.restore_version("old_id", None).await.unwrap().file_id, "new_id");
+        assert_eq!(
+            client.restore_version("large_old_id", None).await.unwrap().file_id,
+            "large_id"
+        );
+
+        let requests: Vec<_> = recorder.interactions().into_iter().map(|i| i.request).collect();
+
+        let Some(RecordedBody::Json(ref copy)) = requests[2].body else {
+            panic!("expected a JSON body")
+        };
+        assert_eq!(copy["sourceFileId"], "old_id");
+        assert_eq!(copy["destinationBucketId"], "bucket_id");
+        assert_eq!(copy["fileName"], "a.txt");
+
+        for request in &requests[5..7] {
+            let Some(RecordedBody::Json(ref copy)) = request.body else {
+                panic!("expected a JSON body")
+            };
+            assert_eq!(copy["sourceFileId"], "large_old_id");
+        }
+    }
+
+    #[cfg(feature = "object_store")]
+    #[test]
+    fn test_object_store_preconditions() {
+        use ::object_store::{path::Path, Error, GetOptions, ObjectMeta};
+
+        let time = |millis| chrono::DateTime::from_timestamp_millis(millis).unwrap();
+
+        let meta = ObjectMeta {
+            location: Path::from("a.txt"),
+            last_modified: time(2000),
+            size: 10,
+            e_tag: Some("file_id".to_owned()),
+            version: Some("file_id".to_owned()),
+        };
+
+        let check = |options: GetOptions| object_store::check_preconditions(&options, &meta);
+
+        assert!(check(GetOptions::default()).is_ok());
+        assert!(check(GetOptions {
+            if_match: Some("other, file_id".into()),
+            ..Default::default()
+        })
+        .is_ok());
+        assert!(check(GetOptions {
+            if_match: Some("*".into()),
+            ..Default::default()
+        })
+        .is_ok());
+        assert!(matches!(
+            check(GetOptions {
+                if_match: Some("other".into()),
+                ..Default::default()
+            }),
+            Err(Error::Precondition { .. })
+        ));
+        assert!(matches!(
+            check(GetOptions {
+                if_unmodified_since: Some(time(1000)),
+                ..Default::default()
+            }),
+            Err(Error::Precondition { .. })
+        ));
+
+        // if_match takes precedence over if_unmodified_since
+        assert!(check(GetOptions {
+            if_match: Some("file_id".into()),
+            if_unmodified_since: Some(time(1000)),
+            ..Default::default()
+        })
+        .is_ok());
+
+        assert!(matches!(
+            check(GetOptions {
+                if_none_match: Some("file_id".into()),
+                ..Default::default()
+            }),
+            Err(Error::NotModified { .. })
+        ));
+        assert!(check(GetOptions {
+            if_none_match: Some("other".into()),
+            ..Default::default()
+        })
+        .is_ok());
+        assert!(matches!(
+            check(GetOptions {
+                if_modified_since: Some(time(2000)),
+                ..Default::default()
+            }),
+            Err(Error::NotModified { .. })
+        ));
+        assert!(check(GetOptions {
+            if_modified_since: Some(time(1000)),
+            ..Default::default()
+        })
+        .is_ok());
+    }
+
+    #[cfg(feature = "object_store")]
+    #[test]
+    fn test_object_store_ranges() {
+        use ::object_store::{path::Path, GetRange};
+
+        let location = Path::from("a.txt");
+        let resolve = |range: Option<GetRange>, size| object_store::resolve_range(range.as_ref(), size, &location);
+
+        assert_eq!(resolve(None, 10).unwrap(), 0..10);
+        assert_eq!(resolve(Some(GetRange::Bounded(2..5)), 10).unwrap(), 2..5);
+        assert_eq!(resolve(Some(GetRange::Bounded(2..50)), 10).unwrap(), 2..10);
+        assert_eq!(resolve(Some(GetRange::Offset(4)), 10).unwrap(), 4..10);
+        assert_eq!(resolve(Some(GetRange::Suffix(3)), 10).unwrap(), 7..10);
+        assert_eq!(resolve(Some(GetRange::Suffix(30)), 10).unwrap(), 0..10);
+        assert_eq!(resolve(None, 0).unwrap(), 0..0);
+
+        assert!(resolve(Some(GetRange::Offset(10)), 10).is_err());
+        assert!(resolve(Some(GetRange::Bounded(12..15)), 10).is_err());
+    }
+
+    #[cfg(feature = "object_store")]
+    #[tokio::test]
+    async fn test_object_store_delete() {
+        use ::object_store::{path::Path, ObjectStore};
+        use serde_json::json;
+
+        let version = |id: &str, name: &str| json!({ "fileId": id, "fileName": name, "action": "upload", "contentLength": 1 });
+
+        let delete = |id: &str| {
+            fixture::api_with(
+                "b2_delete_file_version",
+                json!({ "fileId": id, "fileName": "a/b.txt", "bypassGovernance": false }),
+                json!({ "fileId": id, "fileName": "a/b.txt" }),
+            )
+        };
+
+        let (client, replay) = fixture::client(
+            json!({}),
+            vec![
+                fixture::get(
+                    "b2_list_file_versions?bucketId=bucket_id&startFileName=a%2Fb.txt\
+                     &maxFileCount=1000&prefix=a%2Fb.txt",
+                    json!({
+                        "files": [version("v1", "a/b.txt"), version("v2", "a/b.txt")],
+                        "nextFileName": "a/b.txt",
+                        "nextFileId": "v3",
+                    }),
+                ),
+                delete("v1"),
+                delete("v2"),
+                fixture::get(
+                    "b2_list_file_versions?bucketId=bucket_id&startFileName=a%2Fb.txt&startFileId=v3\
+                     &maxFileCount=1000&prefix=a%2Fb.txt",
+                    json!({
+                        "files": [version("v3", "a/b.txt"), version("v4", "a/b.txt.bak")],
+                        "nextFileName": "a/b.txt.bak",
+                        "nextFileId": "v5",
+                    }),
+                ),
+                delete("v3"),
+            ],
+        )
+        .await;
+
+        let store = object_store::B2ObjectStore::new(client, Some("bucket_id"));
+        store.delete(&Path::from("a/b.txt")).await.unwrap();
+
+        // every version is deleted, and listing stops once the names no longer match
+        replay.finish().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_downloaded_file() {
+        use futures_util::TryStreamExt;
+
+        let (client, replay) = fixture::client(
+            serde_json::json!({}),
+            (0..3).map(|_| fixture::download("fileId=file_id", "file.txt", "contents")).collect(),
+        )
+        .await;
+
+        let download = || client.download_file(DownloadFileBy::FileId("file_id"), None, None);
+
+        let mut file = download().await.unwrap();
+        assert_eq!(file.status(), reqwest::StatusCode::OK);
+        assert_eq!(file.headers()["content-type"], "text/plain");
+        assert_eq!(file.info.file_name, "file.txt");
+
+        let mut contents = Vec::new();
+        while let Some(chunk) = file.chunk().await.unwrap() {
+            contents.extend_from_slice(&chunk);
+        }
+        assert_eq!(contents, b"contents");
+        assert!(file.chunk().await.unwrap().is_none());
+
+        let chunks: Vec<_> = download().await.unwrap().bytes_stream().try_collect().await.unwrap();
+        assert_eq!(chunks.concat(), b"contents");
+
+        let resp = download().await.unwrap().into_reqwest();
+        assert_eq!(resp.text().await.unwrap(), "contents");
+
+        replay.finish().unwrap();
+    }
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn test_blocking_download_read() {
+        use std::io::Read;
+
+        let replay = Arc::new(replay::ReplayTransport::new(vec![
+            fixture::authorize(serde_json::json!({})),
+            fixture::download("fileId=file_id", "file.txt", "hello, world"),
+            fixture::download("fileId=file_id", "file.txt", "hello, world"),
+        ]));
+
+        let client =
+            blocking::Client::authorize(ClientBuilder::new("key_id", "key").transport(replay.clone())).unwrap();
+
+        // reads are split to fit the buffer, and the end of the body reads as 0
+        let mut file = client.download_file(DownloadFileBy::FileId("file_id"), None, None).unwrap();
+        let (mut buf, mut reads) = ([0; 5], Vec::new());
+
+        loop {
+            match file.read(&mut buf).unwrap() {
+                0 => break,
+                n => reads.push(String::from_utf8(buf[..n].to_vec()).unwrap()),
+            }
+        }
+
+        assert_eq!(reads, ["hello", ", wor", "ld"]);
+        assert_eq!(file.read(&mut buf).unwrap(), 0);
+        assert_eq!(file.read(&mut []).unwrap(), 0);
+
+        // the rest of a partially read chunk comes before the rest of the body
+        let mut file = client.download_file(DownloadFileBy::FileId("file_id"), None, None).unwrap();
+        assert_eq!(file.read(&mut buf).unwrap(), 5);
+        assert_eq!(&file.bytes().unwrap()[..], b", world");
+
+        replay.finish().unwrap();
+    }
+
+    #[test]
+    fn test_copy_part_ranges() {
+        use std::num::NonZeroU32;
+
+        let parts = copy::part_ranges(250, 100);
+
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[0], (NonZeroU32::new(1).unwrap(), 0..=99));
+        assert_eq!(parts[2], (NonZeroU32::new(3).unwrap(), 200..=249));
+
+        // never more than 10000 parts
+        let parts = copy::part_ranges(1_000_000, 10);
+
+        assert_eq!(parts.len(), 10_000);
+        assert_eq!(parts.last().unwrap().1, 999_900..=999_999);
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_sync_local_relative() {
+        use sync::local_relative;
+
+        assert_eq!(local_relative("assets/a/b.png", "assets/").as_deref(), Some("a/b.png"));
+        assert_eq!(local_relative("other/b.png", "assets/"), None);
+        assert_eq!(local_relative("assets/", "assets/"), None);
+        assert_eq!(local_relative("assets/../b.png", "assets/"), None);
+        assert_eq!(local_relative("assets//b.png", "assets/"), None);
+        assert_eq!(local_relative("assets/a\\b.png", "assets/"), None);
+    }
+
+    #[test]
+    fn test_token_cache() {
+        use token_cache::{CachedToken, MemoryTokenCache, TokenCache};
+
+        let account: models::B2Authorized = serde_json::from_str(
+            r#"{
+                "accountId": "account",
+                "authorizationToken": "token",
+                "apiInfo": {
+                    "storageApi": {
+                        "apiUrl": "https://api.example.com",
+                        "downloadUrl": "https://f000.example.com",
+                        "recommendedPartSize": 100000000,
+                        "absoluteMinimumPartSize": 5000000,
+                        "s3ApiUrl": "https://s3.example.com",
+                        "capabilities": ["listFiles", "readFiles"],
+                        "bucketId": null,
+                        "bucketName": null,
+                        "namePrefix": null
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let cache = MemoryTokenCache::new();
+
+        CachedToken::store(&cache, "key_id", &account);
+
+        let (cached, _) = CachedToken::load(&cache, "key_id").unwrap();
+        assert_eq!(cached.auth_token, "token");
+        assert!(cached.allowed(B2Capability::READ_FILES));
+
+        assert!(CachedToken::load(&cache, "other_key_id").is_none());
+
+        // a token stored under the wrong key ID is not used
+        cache.store("other_key_id", &cache.load("key_id").unwrap());
+        assert!(CachedToken::load(&cache, "other_key_id").is_none());
+
+        // a corrupt token that cannot be sent as a header is a cache miss
+        let mut corrupt = account;
+        corrupt.auth_token = "bad\ntoken".into();
+
+        CachedToken::store(&cache, "key_id", &corrupt);
+        assert!(CachedToken::load(&cache, "key_id").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_refresh_before() {
+        use serde_json::json;
+
+        let authorize = |token: &str| {
+            let mut interaction = fixture::authorize(json!({}));
+
+            if let Some(replay::RecordedBody::Json(ref mut body)) = interaction.response.body {
+                body["authorizationToken"] = json!(token);
+            }
+
+            interaction
+        };
+
+        let mut revoked = fixture::api_error("", 401, "bad_auth_token");
+        revoked.request = authorize("").request;
+
+        // refresh 100ms after each authorization
+        let builder = ClientBuilder::new("key_id", "key")
+            .refresh_before(token_cache::MAX_TOKEN_AGE - Duration::from_millis(100));
+
+        let (client, replay) =
+            fixture::client_with(builder, json!({}), vec![authorize("refreshed"), revoked]).await;
+
+        // held by the refresh task
+        assert_eq!(Arc::weak_count(&client.state), 1);
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        replay.finish().unwrap();
+
+        // the refreshed token is kept after the refusal, and the task has stopped instead of retrying
+        assert_eq!(client.state.read().await.account.auth_token, "refreshed");
+        assert_eq!(Arc::weak_count(&client.state), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_single_flight_reauth() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        use futures_util::future::BoxFuture;
+        use serde_json::json;
+        use transport::{HttpTransport, ResponseBody};
+
+        const REQUESTS: usize = 8;
+
+        /// Holds back the first responses until every request has one, so all fail before any retries.
+        struct Gate {
+            inner: Arc<replay::ReplayTransport>,
+            barrier: tokio::sync::Barrier,
+            seen: AtomicUsize,
+        }
+
+        impl HttpTransport for Gate {
+            fn send(
+                &self,
+                request: http::Request<reqwest::Body>,
+            ) -> BoxFuture<'_, Result<http::Response<ResponseBody>, B2Error>> {
+                let gated = request.uri().path().ends_with("b2_get_file_info")
+                    && self.seen.fetch_add(1, Ordering::SeqCst) < REQUESTS;
+
+                Box::pin(async move {
+                    let resp = self.inner.send(request).await;
+
+                    if gated {
+                        self.barrier.wait().await;
+                    }
+
+                    resp
+                })
+            }
+        }
+
+        let expired = || {
+            let mut interaction = fixture::api_error("", 401, "expired_auth_token");
+            interaction.request = fixture::get("b2_get_file_info?fileId=file_id", json!({})).request;
+            interaction
+        };
+
+        let mut interactions = vec![fixture::authorize(json!({}))];
+        interactions.extend((0..REQUESTS).map(|_| expired()));
+        interactions.push(fixture::authorize(json!({})));
+        interactions.extend(
+            (0..REQUESTS)
+                .map(|_| fixture::get("b2_get_file_info?fileId=file_id", fixture::file("file_id", "file.txt"))),
+        );
+
+        let replay = Arc::new(replay::ReplayTransport::new(interactions));
+
+        let gate = Gate {
+            inner: replay.clone(),
+            barrier: tokio::sync::Barrier::new(REQUESTS),
+            seen: AtomicUsize::new(0),
+        };
+
+        let client = ClientBuilder::new("key_id", "key").transport(gate).authorize().await.unwrap();
+
+        let tasks: Vec<_> = (0..REQUESTS)
+            .map(|_| {
+                let client = client.clone();
+                tokio::spawn(async move { client.get_file_info("file_id").await })
+            })
+            .collect();
+
+        for task in tasks {
+            assert_eq!(task.await.unwrap().unwrap().file_name, "file.txt");
+        }
+
+        // a second reauthorization would have found no interaction left to replay
+        replay.finish().unwrap();
+        assert_eq!(client.state.read().await.generation, 1);
+    }
+
+    #[tokio::test]
+    async fn test_rotate_credentials() {
+        use error::KeyRotationError;
+        use serde_json::json;
+
+        let storage = |capabilities: &[&str], bucket_id: &str, name_prefix: &str| {
+            let mut storage = json!({ "capabilities": capabilities, "bucketId": bucket_id });
+            storage["namePrefix"] = json!(name_prefix);
+            storage
+        };
+
+        let (client, replay) = fixture::client(
+            storage(&["listFiles", "readFiles"], "bucket_id", "user/"),
+            vec![
+                fixture::authorize(storage(&["listFiles"], "bucket_id", "user/")),
+                fixture::authorize(storage(&["listFiles", "readFiles"], "other_bucket_id", "user/")),
+                fixture::authorize(storage(&["listFiles", "readFiles"], "bucket_id", "other/")),
+                fixture::authorize(storage(&["listFiles", "readFiles", "writeFiles"], "bucket_id", "user/")),
+            ],
+        )
+        .await;
+
+        let rotate = || async {
+            match client.rotate_credentials("new_key_id", "new_key").await {
+                Err(B2Error::KeyRotation(e)) => *e,
+                res => panic!("expected a key rotation error, found {res:?}"),
+            }
+        };
+
+        let err = rotate().await;
+        assert!(matches!(err, KeyRotationError::MissingCapabilities(c) if c == B2Capability::READ_FILES));
+
+        let err = rotate().await;
+        assert!(matches!(err, KeyRotationError::BucketMismatch { found: Some(b), .. } if b == "other_bucket_id"));
+
+        let err = rotate().await;
+        assert!(matches!(err, KeyRotationError::NamePrefixMismatch { found: Some(p), .. } if p == "other/"));
+
+        // rejected keys are not switched to
+        assert_eq!(client.state.read().await.config.key_id, "key_id");
+
+        // extra capabilities are allowed
+        client.rotate_credentials("new_key_id", "new_key").await.unwrap();
+        replay.finish().unwrap();
+
+        let state = client.state.read().await;
+        assert_eq!(state.config.key_id, "new_key_id");
+        assert_eq!(state.generation, 1);
+    }
+
+    #[tokio::test]
+    async fn test_rotate_key_missing_secret() {
+        use error::KeyRotationError;
+        use serde_json::json;
+
+        let key = json!({ "accountId": "account", "keyName": "rotated", "applicationKeyId": "new_key_id" });
+
+        let (client, replay) = fixture::client(
+            json!({}),
+            vec![
+                fixture::api("b2_create_key", key.clone()),
+                fixture::api_with("b2_delete_key", json!({ "applicationKeyId": "new_key_id" }), key),
+            ],
+        )
+        .await;
+
+        let err = client.rotate_key("rotated", None).await.unwrap_err();
+        replay.finish().unwrap();
+
+        let B2Error::KeyRotation(err) = err else {
+            panic!("expected a key rotation error, found {err:?}")
+        };
+        assert!(matches!(*err, KeyRotationError::MissingSecret(ref id) if id == "new_key_id"));
+
+        // the new key was deleted again, and the old key is still in use
+        assert_eq!(client.state.read().await.config.key_id, "key_id");
+    }
+
+    #[tokio::test]
+    async fn test_registry_routing() {
+        use registry::ClientRegistry;
+        use replay::ReplayTransport;
+        use serde_json::json;
+
+        let restricted = Arc::new(ReplayTransport::new(vec![fixture::authorize(
+            json!({ "bucketId": "assets_id", "bucketName": "assets" }),
+        )]));
+        let unrestricted = Arc::new(ReplayTransport::new(vec![fixture::authorize(json!({}))]));
+
+        let registry = ClientRegistry::with_transport(ReplayTransport::new(Vec::new()))
+            .account(
+                "restricted",
+                ClientBuilder::new("restricted_key_id", "key").transport(restricted.clone()),
+            )
+            .account(
+                "unrestricted",
+                ClientBuilder::new("key_id", "key").transport(unrestricted.clone()),
+            )
+            .route_bucket("logs", "unrestricted");
+
+        let key_id = |client: Client| async move { client.state.read().await.config.key_id.clone() };
+
+        // restricted keys are only routed to once authorized
+        assert!(matches!(registry.for_bucket("assets").await, Err(B2Error::UnknownBucket(b)) if b == "assets"));
+
+        // explicit routes authorize on first use
+        assert_eq!(key_id(registry.for_bucket("logs").await.unwrap()).await, "key_id");
+        unrestricted.finish().unwrap();
+
+        assert!(registry.authorize_all().await.is_empty());
+        restricted.finish().unwrap();
+
+        // by both bucket name and ID
+        assert_eq!(
+            key_id(registry.for_bucket("assets").await.unwrap()).await,
+            "restricted_key_id"
+        );
+        assert_eq!(
+            key_id(registry.for_bucket("assets_id").await.unwrap()).await,
+            "restricted_key_id"
+        );
+
+        assert!(matches!(
+            registry.for_bucket("other").await,
+            Err(B2Error::UnknownBucket(_))
+        ));
+        assert!(matches!(registry.get("missing").await, Err(B2Error::UnknownAccount(a)) if a == "missing"));
+
+        assert!(registry.health().await.iter().all(|h| h.authorized && h.last_error.is_none()));
+    }
+
+    #[tokio::test]
+    async fn test_stall_timeout() {
+        use futures_util::future::BoxFuture;
+        use http_body_util::{BodyExt, Full};
+        use transport::{HttpTransport, RequestBuilder, ResponseBody};
+
+        /// Reads the body a chunk at a time, taking `read_delay` per 64 KiB, hanging forever after `max_chunks`.
+        struct Slow {
+            read_delay: Duration,
+            max_chunks: usize,
+            response_delay: Duration,
+        }
+
+        impl HttpTransport for Slow {
+            fn send(
+                &self,
+                request: http::Request<reqwest::Body>,
+            ) -> BoxFuture<'_, Result<http::Response<ResponseBody>, B2Error>> {
+                Box::pin(async move {
+                    let mut body = request.into_body();
+                    let mut read = 0;
+
+                    while let Some(frame) = body.frame().await {
+                        let frame = frame.map_err(|e| B2Error::Transport(e.into()))?;
+                        read += 1;
+
+                        if read == self.max_chunks {
+                            std::future::pending::<()>().await;
+                        }
+
+                        let len = frame.data_ref().map_or(0, |data| data.len());
+                        tokio::time::sleep(self.read_delay * len.div_ceil(64 * 1024) as u32).await;
+                    }
+
+                    tokio::time::sleep(self.response_delay).await;
+
+                    let body = Full::new(bytes::Bytes::new()).map_err(|never| match never {}).boxed();
+                    Ok(http::Response::new(body))
+                })
+            }
+        }
+
+        let stall = Duration::from_millis(100);
+
+        let chunks = || {
+            let chunks = (0..5).map(|_| Ok::<_, std::io::Error>(bytes::Bytes::from_static(b"chunk")));
+            reqwest::Body::wrap_stream(futures_util::stream::iter(chunks))
+        };
+
+        let send = |body: reqwest::Body, read_delay: u64, max_chunks: usize, response_delay: u64| {
+            let transport = Slow {
+                read_delay: Duration::from_millis(read_delay),
+                max_chunks,
+                response_delay: Duration::from_millis(response_delay),
+            };
+
+            let req = RequestBuilder::new(Arc::new(transport), Method::POST, "https://pod.example.com").body(body);
+
+            stall::watch(
+                req,
+                stall,
+                |req| async move { req.send().await.map(|resp| resp.status()) },
+            )
+        };
+
+        // slower than the stall timeout overall, but progressing within each
+        assert!(send(chunks(), 60, usize::MAX, 0).await.is_ok());
+
+        // a single buffer is sent in slices, so a slow upload of it keeps progressing
+        let large = reqwest::Body::from(bytes::Bytes::from(vec![0; 4 * 64 * 1024]));
+        assert!(send(large, 60, usize::MAX, 0).await.is_ok());
+
+        // the body stops being read part-way through
+        let started = std::time::Instant::now();
+        assert!(matches!(send(chunks(), 10, 2, 0).await, Err(B2Error::Stalled(s)) if s == stall));
+        assert!(started.elapsed() < stall * 3);
+
+        // the body is sent, but the response never arrives in time
+        assert!(matches!(
+            send(chunks(), 0, usize::MAX, 1000).await,
+            Err(B2Error::Stalled(_))
+        ));
+    }
+
+    #[test]
+    fn test_transport_operation() {
+        let op = |url: &str| transport::operation(&url.parse().unwrap()).map(str::to_owned);
+
+        assert_eq!(
+            op("https://api.example.com/b2api/v3/b2_list_file_names?bucketId=x").as_deref(),
+            Some("b2_list_file_names")
+        );
+        assert_eq!(
+            op("https://pod-000.example.com/b2api/v3/b2_upload_part/4_z123/0001").as_deref(),
+            Some("b2_upload_part")
+        );
+        assert_eq!(op("https://f000.example.com/file/bucket/b2_not_an_op.txt"), None);
+    }
+
+    #[test]
+    fn test_transaction_class() {
+        use crate::metrics::TransactionClass;
+
+        let classes = [
+            ("b2_upload_file", TransactionClass::A),
+            ("b2_upload_part", TransactionClass::A),
+            ("b2_delete_file_version", TransactionClass::A),
+            ("b2_finish_large_file", TransactionClass::A),
+            ("b2_get_upload_url", TransactionClass::A),
+            ("b2_download_file_by_id", TransactionClass::B),
+            ("b2_download_file_by_name", TransactionClass::B),
+            ("b2_get_file_info", TransactionClass::B),
+            ("b2_list_file_names", TransactionClass::C),
+            ("b2_list_file_versions", TransactionClass::C),
+            ("b2_authorize_account", TransactionClass::C),
+            ("b2_copy_file", TransactionClass::C),
+            ("b2_list_buckets", TransactionClass::C),
+            // unknown operations are assumed to be the most expensive
+            ("b2_future_operation", TransactionClass::C),
+        ];
+
+        for (op, class) in classes {
+            assert_eq!(TransactionClass::of(op), class, "{op}");
+        }
+
+        assert_eq!(TransactionClass::B.as_str(), "B");
+    }
+
+    #[tokio::test]
+    async fn test_bandwidth_limit() {
+        use futures_util::TryStreamExt;
+
+        let limiter = limit::Limits {
+            bandwidth: std::num::NonZeroU32::new(10_000),
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+
+        // a chunk larger than one second's burst is let through in pieces
+        let chunks = futures_util::stream::iter([Ok::<_, B2Error>(bytes::Bytes::from(vec![1; 15_000]))]);
+
+        let start = std::time::Instant::now();
+        let out: Vec<bytes::Bytes> = limit::Throttled::new(chunks, limiter).try_collect().await.unwrap();
+
+        assert_eq!(out.concat(), vec![1; 15_000]);
+        assert!(start.elapsed() >= Duration::from_millis(400));
+    }
+
+    #[tokio::test]
+    async fn test_max_in_flight() {
+        use serde_json::json;
+
+        let builder = ClientBuilder::new("key_id", "key").max_in_flight(std::num::NonZeroUsize::MIN);
+
+        let (client, replay) = fixture::client_with(
+            builder,
+            json!({}),
+            vec![
+                fixture::download("fileId=file_id", "file.txt", "contents"),
+                fixture::get("b2_get_file_info?fileId=file_id", fixture::file("file_id", "file.txt")),
+            ],
+        )
+        .await;
+
+        // a download being read does not hold the only permit, such as while streaming it into an upload
+        let download = client.download_file(DownloadFileBy::FileId("file_id"), None, None).await.unwrap();
+
+        let info = tokio::time::timeout(Duration::from_secs(1), client.get_file_info("file_id")).await;
+        assert_eq!(
+            info.expect("blocked by an open download").unwrap().file_name,
+            "file.txt"
+        );
+
+        assert_eq!(&download.bytes().await.unwrap()[..], b"contents");
+        replay.finish().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replay() {
+        use replay::{RecordingTransport, ReplayTransport};
+
+        let source = r#"[
+            {
+                "request": {
+                    "method": "GET",
+                    "url": "https://api.backblazeb2.com/b2api/v3/b2_authorize_account"
+                },
+                "response": {
+                    "status": 200,
+                    "headers": { "content-type": "application/json;charset=utf-8" },
+                    "body": { "json": {
+                        "accountId": "account",
+                        "authorizationToken": "secret-token",
+                        "apiInfo": {
+                            "storageApi": {
+                                "apiUrl": "https://api.example.com",
+                                "downloadUrl": "https://f000.example.com",
+                                "recommendedPartSize": 100000000,
+                                "absoluteMinimumPartSize": 5000000,
+                                "s3ApiUrl": "https://s3.example.com",
+                                "capabilities": ["writeKeys"]
+                            }
+                        }
+                    } }
+                }
+            },
+            {
+                "request": {
+                    "method": "POST",
+                    "url": "https://api.example.com/b2api/v3/b2_create_key",
+                    "body": { "json": {
+                        "capabilities": ["listFiles"],
+                        "keyName": "test",
+                        "bucketId": null,
+                        "namePrefix": null,
+                        "validDurationInSeconds": null
+                    } }
+                },
+                "response": {
+                    "status": 200,
+                    "headers": { "content-type": "application/json;charset=utf-8" },
+                    "body": { "json": {
+                        "accountId": "account",
+                        "keyName": "test",
+                        "applicationKeyId": "new_key_id",
+                        "applicationKey": "secret-key",
+                        "capabilities": ["listFiles"]
+                    } }
+                }
+            }
+        ]"#;
+
+        let create =
+            CreateApplicationKey::builder().key_name("test").capabilities(B2Capability::LIST_FILES).build();
+
+        // record a session replayed from the source fixture
+        let recorder = Arc::new(RecordingTransport::new(ReplayTransport::from_json(source).unwrap()));
+        let client = ClientBuilder::new("key_id", "key").transport(recorder.clone()).authorize().await.unwrap();
+
+        let key = client.create_key(&create).await.unwrap();
+        assert_eq!(key.application_key.as_deref(), Some("secret-key"));
+
+        let fixture = recorder.to_json();
+        assert!(!fixture.contains("secret"));
+        assert!(fixture.contains(replay::REDACTED));
+
+        // replay the recorded session
+        let replay = Arc::new(ReplayTransport::from_json(&fixture).unwrap());
+        let counter = Arc::new(metrics::TransactionCounter::new());
+        let client = ClientBuilder::new("key_id", "key")
+            .transport(replay.clone())
+            .metrics(counter.clone())
+            .authorize()
+            .await
+            .unwrap();
+
+        assert!(replay.finish().is_err());
+
+        let key = client.create_key(&create).await.unwrap();
+        assert_eq!(key.application_key_id, "new_key_id");
+        assert_eq!(key.application_key.as_deref(), Some(replay::REDACTED));
+
+        replay.finish().unwrap();
+
+        // both b2_authorize_account and b2_create_key are class C
+        assert_eq!(counter.counts(), metrics::TransactionCounts { a: 0, b: 0, c: 2 });
+        assert_eq!(
+            metrics::TransactionClass::of("b2_upload_part"),
+            metrics::TransactionClass::A
+        );
+
+        let err = client
+            .create_key(&CreateApplicationKey {
+                key_name: "other",
+                ..create
+            })
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            B2Error::Replay(error::ReplayError::UnexpectedRequest { .. })
+        ));
+
+        // SSE-C keys are redacted from both headers and JSON bodies
+        let sse_c = sse::ServerSideEncryptionCustomer::aes256(&[7; 32]);
+
+        let recorder = Arc::new(RecordingTransport::new(ReplayTransport::new(vec![
+            fixture::authorize(serde_json::json!({})),
+            fixture::api("b2_copy_file", fixture::file("copy_id", "copy.txt")),
+            fixture::download("fileId=file_id", "file.txt", "contents"),
+        ])));
+        let client = ClientBuilder::new("key_id", "key").transport(recorder.clone()).authorize().await.unwrap();
+
+        let copy = CopyFile::builder()
+            .source_file_id("file_id")
+            .file_name("copy.txt")
+            .source_encryption(sse_c.clone())
+            .build();
+
+        client.copy_file(&copy).await.unwrap();
+        client.download_file(DownloadFileBy::FileId("file_id"), None, Some(sse_c.clone())).await.unwrap();
+
+        let fixture = recorder.to_json();
+        assert!(!fixture.contains(&sse_c.key));
+        assert!(fixture.contains(&sse_c.key_md5));
+    }
+
+    #[cfg(feature = "b2_account_info")]
+    #[test]
+    fn test_account_info() {
+        let path = std::env::temp_dir().join(format!("yab2_account_info_{}", std::process::id()));
+
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE account (account_id TEXT, application_key TEXT, application_key_id TEXT);
+             INSERT INTO account VALUES ('account', 'secret', 'key_id');",
+        )
+        .unwrap();
+
+        let builder = ClientBuilder::from_account_info_path(&path).unwrap();
+        assert_eq!(builder.auth, models::create_auth_header("key_id", "secret"));
+
+        conn.execute("DELETE FROM account", []).unwrap();
+        assert!(matches!(
+            ClientBuilder::from_account_info_path(&path),
+            Err(error::CredentialsError::NoAccount(_))
+        ));
+
+        drop(conn);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_auth() {
         dotenv::dotenv().ok();
 
         let app_id = std::env::var("APP_ID").expect("APP_ID not found in .env");
@@ -1335,13 +3792,12 @@ mod tests {
         file.read_to_end(&mut bytes).await.unwrap();
 
         let bytes = bytes::Bytes::from(bytes); // bytes
-        let sha1 = hex::encode(Sha1::new().chain_update(&bytes).finalize());
 
+        // SHA1 is computed by `upload_file_bytes`
         let info = NewFileInfo::builder()
             .file_name("testing/Cargo.toml")
             .content_length(meta.len())
             .content_type("text/plain")
-            .content_sha1(sha1.as_str())
             .build();
 
         let file_info = upload.upload_file_bytes(&info, bytes).await.unwrap();
@@ -1350,7 +3806,7 @@ mod tests {
 
         let resp = client.download_file(DownloadFileBy::FileId(&file_info.file_id), None, None).await.unwrap();
 
-        let text = resp.resp.text().await.unwrap();
+        let text = String::from_utf8(resp.bytes().await.unwrap().to_vec()).unwrap();
 
         println!("OUTPUT: {text}");
     }
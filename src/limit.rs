@@ -0,0 +1,153 @@
+//! Client-side rate limits, concurrency caps and bandwidth limits, shared by every clone of a [`Client`](crate::Client).
+
+use std::future::Future;
+use std::num::NonZeroU32;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt};
+use governor::clock::Clock;
+use governor::{DefaultDirectRateLimiter, Quota, RateLimiter};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::metrics::TransactionClass;
+
+/// Limits configured on a [`ClientBuilder`](crate::ClientBuilder).
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct Limits {
+    /// Requests per second for each of [`TransactionClass::A`], `B` and `C`.
+    pub per_class: [Option<NonZeroU32>; 3],
+    pub max_in_flight: Option<std::num::NonZeroUsize>,
+    pub bandwidth: Option<NonZeroU32>,
+}
+
+const fn class_index(class: TransactionClass) -> usize {
+    match class {
+        TransactionClass::A => 0,
+        TransactionClass::B => 1,
+        TransactionClass::C => 2,
+    }
+}
+
+impl Limits {
+    pub fn set_rate(&mut self, class: TransactionClass, per_second: NonZeroU32) {
+        self.per_class[class_index(class)] = Some(per_second);
+    }
+
+    /// Builds the shared limiter, if any limits are set.
+    pub fn build(&self) -> Option<Arc<Limiter>> {
+        if self.per_class.iter().all(Option::is_none) && self.max_in_flight.is_none() && self.bandwidth.is_none() {
+            return None;
+        }
+
+        Some(Arc::new(Limiter {
+            per_class: self.per_class.map(|rate| rate.map(|rate| RateLimiter::direct(Quota::per_second(rate)))),
+            in_flight: self.max_in_flight.map(|max| Arc::new(Semaphore::new(max.get()))),
+            bandwidth: self.bandwidth.map(|rate| (RateLimiter::direct(Quota::per_second(rate)), rate)),
+        }))
+    }
+}
+
+/// The state of the limits, shared by every clone of a client.
+pub(crate) struct Limiter {
+    per_class: [Option<DefaultDirectRateLimiter>; 3],
+    in_flight: Option<Arc<Semaphore>>,
+
+    /// One cell per byte, with a burst of one second's worth, along with that burst size.
+    bandwidth: Option<(DefaultDirectRateLimiter, NonZeroU32)>,
+}
+
+impl Limiter {
+    /// Waits until a request of the given class can be sent, returning a permit to hold while it is in flight.
+    pub async fn acquire(&self, class: TransactionClass) -> Option<OwnedSemaphorePermit> {
+        if let Some(ref limiter) = self.per_class[class_index(class)] {
+            limiter.until_ready().await;
+        }
+
+        match self.in_flight {
+            // the semaphore is never closed
+            Some(ref semaphore) => semaphore.clone().acquire_owned().await.ok(),
+            None => None,
+        }
+    }
+
+    /// Whether the bandwidth of bodies is limited.
+    pub fn limits_bandwidth(&self) -> bool {
+        self.bandwidth.is_some()
+    }
+}
+
+/// A stream of bytes limited to the bandwidth of a [`Limiter`].
+pub(crate) struct Throttled<S> {
+    inner: S,
+    limiter: Arc<Limiter>,
+
+    /// A chunk waiting for `pending` more bytes of bandwidth before being yielded.
+    chunk: Option<Bytes>,
+    pending: u64,
+    sleep: Option<Pin<Box<tokio::time::Sleep>>>,
+}
+
+impl<S> Throttled<S> {
+    pub fn new(inner: S, limiter: Arc<Limiter>) -> Self {
+        Throttled {
+            inner,
+            limiter,
+            chunk: None,
+            pending: 0,
+            sleep: None,
+        }
+    }
+}
+
+impl<S, E> Stream for Throttled<S>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+{
+    type Item = Result<Bytes, E>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+
+        let Some((ref bandwidth, burst)) = this.limiter.bandwidth else {
+            return this.inner.poll_next_unpin(cx);
+        };
+
+        loop {
+            if let Some(ref mut sleep) = this.sleep {
+                ready!(sleep.as_mut().poll(cx));
+                this.sleep = None;
+            }
+
+            if this.chunk.is_none() {
+                match ready!(this.inner.poll_next_unpin(cx)) {
+                    Some(Ok(chunk)) => {
+                        this.pending = chunk.len() as u64;
+                        this.chunk = Some(chunk);
+                    }
+                    res => return Poll::Ready(res),
+                }
+            }
+
+            // chunks larger than the burst size are acquired in pieces
+            while let Some(n) = NonZeroU32::new(this.pending.min(burst.get() as u64) as u32) {
+                match bandwidth.check_n(n) {
+                    Ok(Ok(())) => this.pending -= n.get() as u64,
+                    Ok(Err(not_until)) => {
+                        let wait = not_until.wait_time_from(bandwidth.clock().now());
+                        this.sleep = Some(Box::pin(tokio::time::sleep(wait)));
+                        break;
+                    }
+                    // not possible, as `n` is at most the burst size
+                    Err(_) => this.pending = 0,
+                }
+            }
+
+            if this.pending == 0 {
+                return Poll::Ready(this.chunk.take().map(Ok));
+            }
+        }
+    }
+}
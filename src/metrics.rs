@@ -0,0 +1,274 @@
+//! Metrics for B2 operations, including the transaction classes Backblaze bills by.
+//!
+//! Set a [`Metrics`] implementation with [`ClientBuilder::metrics`](crate::ClientBuilder::metrics), such as
+//! a [`TransactionCounter`] to estimate the cost of a job, or with the `metrics` feature, a [`MetricsRecorder`]
+//! reporting to the [`metrics`](::metrics) crate. Both can be used at once as a tuple.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The class of a B2 transaction, which determines how it is billed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TransactionClass {
+    /// Uploads, deletes and starting or finishing large files, which are free.
+    A,
+
+    /// Downloads and `b2_get_file_info`.
+    B,
+
+    /// Listing, authorizing, creating, copying and updating.
+    C,
+}
+
+impl TransactionClass {
+    /// Returns the class of a B2 operation by name, such as `b2_upload_part`.
+    ///
+    /// Unknown operations are assumed to be [`TransactionClass::C`], the most expensive class.
+    pub fn of(operation: &str) -> TransactionClass {
+        match operation {
+            "b2_cancel_large_file"
+            | "b2_delete_bucket"
+            | "b2_delete_file_version"
+            | "b2_delete_key"
+            | "b2_finish_large_file"
+            | "b2_get_upload_part_url"
+            | "b2_get_upload_url"
+            | "b2_start_large_file"
+            | "b2_update_file_legal_hold"
+            | "b2_update_file_retention"
+            | "b2_upload_file"
+            | "b2_upload_part" => TransactionClass::A,
+
+            "b2_download_file_by_id" | "b2_download_file_by_name" | "b2_get_file_info" => TransactionClass::B,
+
+            _ => TransactionClass::C,
+        }
+    }
+
+    /// Returns the name of the class, `"A"`, `"B"` or `"C"`.
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            TransactionClass::A => "A",
+            TransactionClass::B => "B",
+            TransactionClass::C => "C",
+        }
+    }
+}
+
+/// A completed request, see [`Metrics::request`].
+#[derive(Debug, Clone)]
+pub struct RequestMetrics<'a> {
+    /// The name of the B2 operation, such as `b2_list_file_names`.
+    pub operation: &'a str,
+
+    /// The transaction class of the operation.
+    pub class: TransactionClass,
+
+    /// The HTTP status code of the response.
+    pub status: u16,
+
+    /// The size of the request body, in bytes.
+    pub bytes_sent: u64,
+
+    /// The size of the response body, in bytes, as given by its `Content-Length`.
+    pub bytes_received: u64,
+
+    /// The time from sending the request to receiving the response headers.
+    pub latency: Duration,
+}
+
+/// Receives metrics from a [`Client`](crate::Client).
+///
+/// Every method does nothing by default, so implementations only need to override what they record.
+pub trait Metrics: Send + Sync + 'static {
+    /// Called for every response received, successful or not.
+    ///
+    /// Requests that fail without a response, such as on connection errors, are not reported.
+    fn request(&self, request: &RequestMetrics<'_>) {
+        _ = request;
+    }
+
+    /// Called before an operation is attempted again, after reauthorizing, refreshing an upload URL,
+    /// or authorization being rejected by the circuit breaker.
+    fn retry(&self) {}
+
+    /// Called when the client has been reauthorized, after a 401 Unauthorized response or in the background.
+    fn reauthorized(&self) {}
+
+    /// Called when an upload URL has been replaced after a 401 Unauthorized response.
+    fn upload_url_refreshed(&self) {}
+}
+
+impl<T: Metrics + ?Sized> Metrics for Arc<T> {
+    fn request(&self, request: &RequestMetrics<'_>) {
+        (**self).request(request)
+    }
+
+    fn retry(&self) {
+        (**self).retry()
+    }
+
+    fn reauthorized(&self) {
+        (**self).reauthorized()
+    }
+
+    fn upload_url_refreshed(&self) {
+        (**self).upload_url_refreshed()
+    }
+}
+
+impl<A: Metrics, B: Metrics> Metrics for (A, B) {
+    fn request(&self, request: &RequestMetrics<'_>) {
+        self.0.request(request);
+        self.1.request(request);
+    }
+
+    fn retry(&self) {
+        self.0.retry();
+        self.1.retry();
+    }
+
+    fn reauthorized(&self) {
+        self.0.reauthorized();
+        self.1.reauthorized();
+    }
+
+    fn upload_url_refreshed(&self) {
+        self.0.upload_url_refreshed();
+        self.1.upload_url_refreshed();
+    }
+}
+
+/// The number of transactions made in each class, see [`TransactionCounter`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TransactionCounts {
+    /// Class A transactions.
+    pub a: u64,
+    /// Class B transactions.
+    pub b: u64,
+    /// Class C transactions.
+    pub c: u64,
+}
+
+impl TransactionCounts {
+    /// Returns the number of transactions in the given class.
+    pub fn get(&self, class: TransactionClass) -> u64 {
+        match class {
+            TransactionClass::A => self.a,
+            TransactionClass::B => self.b,
+            TransactionClass::C => self.c,
+        }
+    }
+
+    /// Returns the number of transactions in all classes.
+    pub fn total(&self) -> u64 {
+        self.a + self.b + self.c
+    }
+}
+
+/// A running count of transactions per class, for estimating the cost of a job.
+///
+/// Share it with the client through an [`Arc`] to read the counts while the client is in use:
+///
+/// ```ignore
+/// let counter = Arc::new(TransactionCounter::new());
+/// let client = ClientBuilder::new(&key_id, &key).metrics(counter.clone()).authorize().await?;
+///
+/// run_job(&client).await?;
+///
+/// let counts = counter.reset();
+/// println!("{} class B, {} class C transactions", counts.b, counts.c);
+/// ```
+#[derive(Debug, Default)]
+pub struct TransactionCounter {
+    a: AtomicU64,
+    b: AtomicU64,
+    c: AtomicU64,
+}
+
+impl TransactionCounter {
+    /// Creates a new counter starting from zero.
+    pub fn new() -> Self {
+        TransactionCounter::default()
+    }
+
+    fn counter(&self, class: TransactionClass) -> &AtomicU64 {
+        match class {
+            TransactionClass::A => &self.a,
+            TransactionClass::B => &self.b,
+            TransactionClass::C => &self.c,
+        }
+    }
+
+    /// Returns the current counts.
+    pub fn counts(&self) -> TransactionCounts {
+        TransactionCounts {
+            a: self.a.load(Ordering::Relaxed),
+            b: self.b.load(Ordering::Relaxed),
+            c: self.c.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Resets the counts to zero, returning the counts before the reset.
+    pub fn reset(&self) -> TransactionCounts {
+        TransactionCounts {
+            a: self.a.swap(0, Ordering::Relaxed),
+            b: self.b.swap(0, Ordering::Relaxed),
+            c: self.c.swap(0, Ordering::Relaxed),
+        }
+    }
+}
+
+impl Metrics for TransactionCounter {
+    fn request(&self, request: &RequestMetrics<'_>) {
+        self.counter(request.class).fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Reports metrics to the global recorder of the [`metrics`](::metrics) crate.
+///
+/// The following metrics are recorded:
+///
+/// - `b2_requests_total` (counter, labeled by `operation`, `class` and `status`)
+/// - `b2_transactions_total` (counter, labeled by `class`)
+/// - `b2_bytes_sent_total` and `b2_bytes_received_total` (counters, labeled by `operation`)
+/// - `b2_request_duration_seconds` (histogram, labeled by `operation`)
+/// - `b2_retries_total`, `b2_reauthorizations_total` and `b2_upload_url_refreshes_total` (counters)
+#[cfg(feature = "metrics")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MetricsRecorder;
+
+#[cfg(feature = "metrics")]
+impl Metrics for MetricsRecorder {
+    fn request(&self, request: &RequestMetrics<'_>) {
+        let operation = request.operation.to_owned();
+        let class = request.class.as_str();
+
+        ::metrics::counter!(
+            "b2_requests_total",
+            "operation" => operation.clone(),
+            "class" => class,
+            "status" => request.status.to_string(),
+        )
+        .increment(1);
+
+        ::metrics::counter!("b2_transactions_total", "class" => class).increment(1);
+        ::metrics::counter!("b2_bytes_sent_total", "operation" => operation.clone()).increment(request.bytes_sent);
+        ::metrics::counter!("b2_bytes_received_total", "operation" => operation.clone())
+            .increment(request.bytes_received);
+        ::metrics::histogram!("b2_request_duration_seconds", "operation" => operation).record(request.latency);
+    }
+
+    fn retry(&self) {
+        ::metrics::counter!("b2_retries_total").increment(1);
+    }
+
+    fn reauthorized(&self) {
+        ::metrics::counter!("b2_reauthorizations_total").increment(1);
+    }
+
+    fn upload_url_refreshed(&self) {
+        ::metrics::counter!("b2_upload_url_refreshes_total").increment(1);
+    }
+}
@@ -0,0 +1,385 @@
+//! Copying every file under a prefix from one bucket to another.
+//!
+//! Within the same account, files are copied on the server side with `b2_copy_file`/`b2_copy_part`.
+//! Across accounts, each file is downloaded from the source and streamed directly into an upload
+//! to the destination, so nothing is buffered on disk or in memory.
+//!
+//! See [`Client::migrate`] for more information.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use bytes::Bytes;
+use futures_util::stream::{self, StreamExt};
+use smol_str::SmolStr;
+
+use crate::copy::{cancel_after, finish, legal_hold_of, part_ranges, retention_of};
+use crate::models::{B2FileAction, B2FileInfo};
+use crate::*;
+
+/// Options for migrating files with [`Client::migrate`].
+///
+/// See the documentation for [`MigrateOptions::builder`] for more information.
+#[derive(typed_builder::TypedBuilder)]
+#[builder(doc)]
+pub struct MigrateOptions<'a> {
+    /// The ID of the bucket to copy from. If `None`, the source client's default bucket will be used.
+    #[builder(default, setter(into))]
+    pub source_bucket_id: Option<&'a str>,
+
+    /// Only files whose names start with this prefix are copied.
+    #[builder(default)]
+    pub source_prefix: &'a str,
+
+    /// The ID of the bucket to copy into. If `None`, the destination client's default bucket will be used.
+    #[builder(default, setter(into))]
+    pub destination_bucket_id: Option<&'a str>,
+
+    /// Replaces [`source_prefix`](MigrateOptions::source_prefix) in the names of copied files.
+    #[builder(default)]
+    pub destination_prefix: &'a str,
+
+    /// Resume a previous run by skipping source files with names up to and including this name,
+    /// usually the last [`MigrateReport::checkpoint`].
+    #[builder(default, setter(into))]
+    pub start_after: Option<&'a str>,
+
+    /// Called with the name of a source file once it and every file before it have been processed,
+    /// so it can be persisted and later passed to [`start_after`](MigrateOptions::start_after).
+    ///
+    /// Files that failed are included in [`MigrateReport::failed`] rather than holding back the checkpoint.
+    #[builder(default, setter(strip_option))]
+    pub checkpoint: Option<&'a (dyn Fn(&str) + Send + Sync)>,
+
+    /// The maximum number of files to copy at once.
+    ///
+    /// If set to 0, the default of 4 files will be used.
+    #[builder(default)]
+    pub max_simultaneous_files: u8,
+
+    /// The SSE-C key the source files are encrypted with, if any.
+    #[builder(default, setter(into))]
+    pub source_encryption: Option<sse::ServerSideEncryptionCustomer>,
+
+    /// The server-side encryption to use for the copied files.
+    #[builder(default, setter(into))]
+    pub encryption: sse::ServerSideEncryption,
+}
+
+impl std::fmt::Debug for MigrateOptions<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MigrateOptions")
+            .field("source_bucket_id", &self.source_bucket_id)
+            .field("source_prefix", &self.source_prefix)
+            .field("destination_bucket_id", &self.destination_bucket_id)
+            .field("destination_prefix", &self.destination_prefix)
+            .field("start_after", &self.start_after)
+            .field("checkpoint", &self.checkpoint.is_some())
+            .field("max_simultaneous_files", &self.max_simultaneous_files)
+            .field("source_encryption", &self.source_encryption)
+            .field("encryption", &self.encryption)
+            .finish()
+    }
+}
+
+/// The results of [`Client::migrate`].
+#[derive(Default, Debug)]
+pub struct MigrateReport {
+    /// The number of files copied.
+    pub copied: u64,
+
+    /// The total size of the files copied, in bytes.
+    pub bytes_copied: u64,
+
+    /// The number of files skipped because they already exist in the destination with the same SHA1 hash.
+    pub skipped: u64,
+
+    /// Names of source files that failed to copy, and the error that occurred.
+    pub failed: Vec<(SmolStr, B2Error)>,
+
+    /// The name of the last source file processed, to resume from with [`MigrateOptions::start_after`].
+    pub checkpoint: Option<SmolStr>,
+}
+
+impl MigrateReport {
+    /// Returns `true` if every file was copied or skipped successfully.
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+/// Looks up destination files in name order, listing them one page at a time as needed.
+struct DestinationCursor<'a> {
+    client: &'a Client,
+    bucket_id: Option<&'a str>,
+    prefix: &'a str,
+    files: VecDeque<B2FileInfo>,
+    done: bool,
+}
+
+impl DestinationCursor<'_> {
+    /// Finds the destination file with the given name.
+    ///
+    /// Must be called with names in ascending order.
+    async fn find(&mut self, file_name: &str) -> Result<Option<&B2FileInfo>, B2Error> {
+        loop {
+            while self.files.front().is_some_and(|file| file.file_name.as_str() < file_name) {
+                self.files.pop_front();
+            }
+
+            if !self.files.is_empty() || self.done {
+                break;
+            }
+
+            let list = self
+                .client
+                .list_files(
+                    &ListFiles::builder()
+                        .bucket_id(self.bucket_id)
+                        .prefix((!self.prefix.is_empty()).then_some(self.prefix))
+                        .start_file_name(file_name)
+                        .max_file_count(1000)
+                        .build(),
+                )
+                .await?;
+
+            self.done = list.next_file_name.is_none();
+            self.files.extend(list.files);
+
+            if self.files.is_empty() {
+                self.done = true;
+            }
+        }
+
+        Ok(self.files.front().filter(|file| file.file_name == file_name))
+    }
+}
+
+/// Creates a body that streams the response, which can only be sent once.
+///
+/// Retrying the upload with this body fails, rather than uploading partial or empty content.
+fn stream_once(download: DownloadedFile) -> impl Fn() -> reqwest::Body {
+    let download = Mutex::new(Some(download));
+
+    move || match download.lock().unwrap_or_else(|e| e.into_inner()).take() {
+        Some(download) => reqwest::Body::wrap(download.into_body()),
+        None => reqwest::Body::wrap_stream(stream::once(async {
+            Err::<Bytes, _>(std::io::Error::other("download stream cannot be retried"))
+        })),
+    }
+}
+
+impl Client {
+    /// Copies every file under [`MigrateOptions::source_prefix`] in the source bucket of this client
+    /// to the destination bucket of `destination`, which may be a clone of this client.
+    ///
+    /// Content type, file info, file retention and legal hold settings are preserved, though the
+    /// latter two are only readable with the `readFileRetentions` and `readFileLegalHolds` capabilities.
+    ///
+    /// Files that already exist in the destination with the same SHA1 hash are skipped. Large files
+    /// only have a known hash if they were uploaded with the `large_file_sha1` file info,
+    /// see [`B2FileInfo::known_sha1`].
+    ///
+    /// If both clients are authorized for the same account, files are copied on the server side,
+    /// otherwise each file is downloaded and streamed straight into an upload, in parts for large files.
+    ///
+    /// Files are listed in name order, and after each page of files has been processed, the
+    /// name of the last file is passed to [`MigrateOptions::checkpoint`], so an interrupted run can be
+    /// resumed with [`MigrateOptions::start_after`].
+    ///
+    /// An error is only returned if listing files fails, otherwise failures are collected
+    /// for each file in the returned [`MigrateReport`], and the run can be resumed from
+    /// [`MigrateReport::checkpoint`].
+    pub async fn migrate(
+        &self,
+        destination: &Client,
+        options: &MigrateOptions<'_>,
+    ) -> Result<MigrateReport, B2Error> {
+        let max_simultaneous_files = match options.max_simultaneous_files {
+            0 => 4,
+            n => n,
+        };
+
+        let same_account = {
+            let (source, destination) = (self.state.read().await, destination.state.read().await);
+
+            source.account.account_id == destination.account.account_id
+        };
+
+        let mut report = MigrateReport::default();
+
+        let mut cursor = DestinationCursor {
+            client: destination,
+            bucket_id: options.destination_bucket_id,
+            prefix: options.destination_prefix,
+            files: VecDeque::new(),
+            done: false,
+        };
+
+        let mut start_file_name = options.start_after.map(SmolStr::from);
+
+        loop {
+            let list = self
+                .list_files(
+                    &ListFiles::builder()
+                        .bucket_id(options.source_bucket_id)
+                        .prefix((!options.source_prefix.is_empty()).then_some(options.source_prefix))
+                        .start_file_name(start_file_name.as_deref())
+                        .max_file_count(1000)
+                        .build(),
+                )
+                .await?;
+
+            let Some(last) = list.files.last().map(|file| file.file_name.clone()) else {
+                break;
+            };
+
+            let mut pending = Vec::with_capacity(list.files.len());
+
+            for file in list.files {
+                // `start_file_name` is inclusive
+                if options.start_after.is_some_and(|start_after| file.file_name.as_str() <= start_after) {
+                    continue;
+                }
+
+                if matches!(
+                    file.action,
+                    Some(B2FileAction::Started | B2FileAction::Hidden | B2FileAction::Folder)
+                ) {
+                    continue;
+                }
+
+                let Some(relative) = file.file_name.strip_prefix(options.source_prefix) else {
+                    continue;
+                };
+
+                let file_name = SmolStr::from(format!("{}{relative}", options.destination_prefix));
+
+                let exists = match cursor.find(&file_name).await? {
+                    Some(existing) => existing.known_sha1().is_some_and(|sha1| file.known_sha1() == Some(sha1)),
+                    None => false,
+                };
+
+                match exists {
+                    true => report.skipped += 1,
+                    false => pending.push((file, file_name)),
+                }
+            }
+
+            let copies = stream::iter(pending).map(|(file, file_name)| async move {
+                let res = match same_account {
+                    true => destination
+                        .copy_whole_file(
+                            &file,
+                            options.destination_bucket_id,
+                            &file_name,
+                            options.source_encryption.clone(),
+                            &options.encryption,
+                        )
+                        .await
+                        .map(|_| ()),
+                    false => self.migrate_stream(destination, &file, &file_name, options).await,
+                };
+
+                (file, res)
+            });
+
+            // Box the future to avoid bloating the stack too much
+            let mut copies = Box::pin(copies.buffer_unordered(max_simultaneous_files as usize));
+
+            while let Some((file, res)) = copies.next().await {
+                match res {
+                    Ok(()) => {
+                        report.copied += 1;
+                        report.bytes_copied += file.content_length;
+                    }
+                    Err(e) => report.failed.push((file.file_name, e)),
+                }
+            }
+
+            drop(copies);
+
+            if let Some(checkpoint) = options.checkpoint {
+                checkpoint(&last);
+            }
+
+            report.checkpoint = Some(last);
+
+            match list.next_file_name {
+                Some(next) => start_file_name = Some(next),
+                None => break,
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Downloads a file and streams it into an upload to the destination, in parts for large files.
+    async fn migrate_stream(
+        &self,
+        destination: &Client,
+        file: &B2FileInfo,
+        file_name: &str,
+        options: &MigrateOptions<'_>,
+    ) -> Result<(), B2Error> {
+        let recommended_part_size = destination.state.read().await.account.api.storage.recommended_part_size;
+
+        if file.content_length <= recommended_part_size {
+            let source = DownloadFileBy::FileId(&file.file_id);
+            let download = self.download_file(source, None, options.source_encryption.clone()).await?;
+
+            let info = NewFileInfo {
+                file_name,
+                content_length: file.content_length,
+                content_type: file.content_type.as_deref(),
+                content_sha1: file.known_sha1().map_or(ContentSha1::Compute, ContentSha1::Hex),
+                encryption: options.encryption.clone(),
+                retention: retention_of(file),
+                legal_hold: legal_hold_of(file),
+                file_info: Some(&file.file_info),
+            };
+
+            let mut url = destination.get_upload_url(options.destination_bucket_id).await?;
+
+            return match info.content_sha1 {
+                ContentSha1::Hex(_) => url.upload_file_checked(&info, stream_once(download)).await.map(|_| ()),
+                _ => url.upload_file(&info, stream_once(download)).await.map(|_| ()),
+            };
+        }
+
+        let large = destination
+            .start_large_file_like(file, options.destination_bucket_id, file_name, &options.encryption)
+            .await?;
+
+        let mut url = match large.get_upload_part_url().await {
+            Ok(url) => url,
+            Err(e) => return Err(cancel_after(large, e).await),
+        };
+
+        let mut parts = Vec::new();
+
+        for (part_number, range) in part_ranges(file.content_length, recommended_part_size) {
+            let res = async {
+                let part = NewPartInfo::builder()
+                    .part_number(part_number)
+                    .content_length(range.end() - range.start() + 1)
+                    .encryption(options.encryption.clone())
+                    .build();
+
+                let range = headers::Range::bytes(range).map_err(|_| B2Error::Unknown)?;
+
+                let source = DownloadFileBy::FileId(&file.file_id);
+                let download = self.download_file(source, Some(range), options.source_encryption.clone()).await?;
+
+                large.upload_part(&mut url, &part, stream_once(download)).await
+            };
+
+            match res.await {
+                Ok(part) => parts.push(part),
+                Err(e) => return Err(cancel_after(large, e).await),
+            }
+        }
+
+        finish(large, &parts).await.map(|_| ())
+    }
+}
@@ -20,7 +20,7 @@ pub fn create_auth_header(key_id: &str, key: &str) -> HeaderValue {
         .expect("Unable to create auth header value")
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct B2Authorized {
     /// The identifier for the account.
     #[serde(alias = "accountId")]
@@ -40,13 +40,13 @@ pub struct B2Authorized {
     pub expiration: Option<u64>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct B2ApiInfo {
     #[serde(alias = "storageApi")]
     pub storage: B2StorageApi,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct B2StorageApi {
     /// The URL to use for API calls.
@@ -100,6 +100,10 @@ pub struct B2ApplicationKey {
     /// The ID of the application key.
     pub application_key_id: SmolStr,
 
+    /// The secret part of the key, only returned when the key is created.
+    #[serde(default)]
+    pub application_key: Option<SmolStr>,
+
     #[serde(default)]
     pub bucket_id: Option<SmolStr>,
 
@@ -374,7 +378,7 @@ pub struct B2FileRetentionValue {
     pub retain_until_timestamp: u64,
 }
 
-#[derive(Default, Debug, Deserialize)]
+#[derive(Default, Debug, Clone, Deserialize)]
 pub struct B2ServerSideEncryption {
     #[serde(default)]
     pub algorithm: Option<SmolStr>,
@@ -402,6 +406,23 @@ pub struct B2FileInfo {
     pub upload_timestamp: u64,
 }
 
+impl B2FileInfo {
+    /// Gets the SHA1 hash of the file contents as a hex string, if known.
+    ///
+    /// Large files only have a known hash if it was provided in the `large_file_sha1` file info
+    /// when the file was started. The `unverified:` prefix of client-provided hashes is removed.
+    pub fn known_sha1(&self) -> Option<&str> {
+        let sha1 = match self.content_sha1.as_deref() {
+            None | Some("none") => self.file_info.get("large_file_sha1")?.as_str(),
+            Some(sha1) => sha1,
+        };
+
+        let sha1 = sha1.strip_prefix("unverified:").unwrap_or(sha1);
+
+        (sha1.len() == 40).then_some(sha1)
+    }
+}
+
 #[derive(Default, Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct B2FileInfoList {
@@ -426,7 +447,7 @@ pub struct B2CancelledFileInfo {
     pub account_id: SmolStr,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct B2PartInfo {
     pub file_id: SmolStr,
@@ -0,0 +1,625 @@
+//! [`object_store::ObjectStore`](::object_store::ObjectStore) implementation for a bucket.
+//!
+//! Paths are used as file names as-is, and B2 file IDs are used as both the
+//! [`version`](ObjectMeta::version) and [`e_tag`](ObjectMeta::e_tag) of objects.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+use ::object_store::{
+    path::{Path, DELIMITER},
+    Attribute, Attributes, GetOptions, GetRange, GetResult, GetResultPayload, ListResult, MultipartUpload,
+    ObjectMeta, ObjectStore, PutMode, PutMultipartOpts, PutOptions, PutPayload, PutResult, Result, UploadPart,
+};
+use bytes::Bytes;
+use futures_util::stream::{self, BoxStream, StreamExt, TryStreamExt};
+use smol_str::SmolStr;
+
+use crate::models::{B2FileAction, B2FileInfo, B2PartInfo};
+use crate::*;
+
+const STORE: &str = "B2";
+
+/// An [`ObjectStore`] backed by a [`Client`] and a bucket.
+///
+/// Created with [`B2ObjectStore::new`], and cheap to clone.
+#[derive(Clone)]
+pub struct B2ObjectStore {
+    client: Client,
+    bucket_id: Option<SmolStr>,
+}
+
+impl B2ObjectStore {
+    /// Creates a new object store for the given bucket.
+    ///
+    /// If `bucket_id` is `None`, the client's default bucket will be used.
+    pub fn new(client: Client, bucket_id: Option<&str>) -> Self {
+        B2ObjectStore {
+            client,
+            bucket_id: bucket_id.map(SmolStr::from),
+        }
+    }
+
+    fn bucket_id(&self) -> Option<&str> {
+        self.bucket_id.as_deref()
+    }
+
+    /// Finds the latest version of a file by name, or a specific version by file ID.
+    async fn find(&self, location: &Path, version: Option<&str>) -> Result<B2FileInfo> {
+        if let Some(file_id) = version {
+            return self.client.get_file_info(file_id).await.map_err(|e| into_error(e, location));
+        }
+
+        let name = location.as_ref();
+
+        let list = self
+            .client
+            .list_files(
+                &ListFiles::builder()
+                    .bucket_id(self.bucket_id())
+                    .prefix(name)
+                    .start_file_name(name)
+                    .max_file_count(1)
+                    .build(),
+            )
+            .await
+            .map_err(|e| into_error(e, location))?;
+
+        match list.files.into_iter().next() {
+            Some(file) if file.file_name == name => Ok(file),
+            _ => Err(not_found(location)),
+        }
+    }
+
+    async fn put_bytes(&self, location: &Path, bytes: Bytes, opts: PutOptions) -> Result<PutResult> {
+        match opts.mode {
+            PutMode::Overwrite => {}
+            PutMode::Create => match self.find(location, None).await {
+                Ok(_) => {
+                    return Err(::object_store::Error::AlreadyExists {
+                        path: location.to_string(),
+                        source: "file already exists".into(),
+                    })
+                }
+                Err(::object_store::Error::NotFound { .. }) => {}
+                Err(e) => return Err(e),
+            },
+            PutMode::Update(_) => return Err(::object_store::Error::NotImplemented),
+        }
+
+        let (content_type, file_info) = attributes(&opts.attributes)?;
+
+        let recommended_part_size = self.client.state.read().await.account.api.storage.recommended_part_size;
+
+        let res = async {
+            if bytes.len() as u64 <= recommended_part_size {
+                let info = NewFileInfo::builder()
+                    .file_name(location.as_ref())
+                    .content_length(bytes.len() as u64)
+                    .content_type(content_type)
+                    .file_info(&file_info)
+                    .build();
+
+                let mut url = self.client.get_upload_url(self.bucket_id()).await?;
+
+                return url.upload_file_bytes(&info, bytes).await;
+            }
+
+            let info = NewLargeFileInfo::builder()
+                .file_name(location.as_ref())
+                .content_type(content_type)
+                .file_info(&file_info)
+                .build();
+
+            let large = self.client.start_large_file(self.bucket_id(), &info).await?;
+            let mut url = large.get_upload_part_url().await?;
+
+            let mut parts = Vec::new();
+
+            for (part_number, range) in copy::part_ranges(bytes.len() as u64, recommended_part_size) {
+                let part = bytes.slice(*range.start() as usize..=*range.end() as usize);
+
+                let info =
+                    NewPartInfo::builder().part_number(part_number).content_length(part.len() as u64).build();
+
+                match large.upload_part_bytes(&mut url, &info, part).await {
+                    Ok(part) => parts.push(part),
+                    Err(e) => {
+                        _ = large.cancel().await;
+                        return Err(e);
+                    }
+                }
+            }
+
+            large.finish(&parts).await
+        };
+
+        let file = res.await.map_err(|e| into_error(e, location))?;
+
+        Ok(PutResult {
+            e_tag: Some(file.file_id.to_string()),
+            version: Some(file.file_id.to_string()),
+        })
+    }
+}
+
+impl fmt::Debug for B2ObjectStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("B2ObjectStore").field("bucket_id", &self.bucket_id).finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for B2ObjectStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.bucket_id {
+            Some(ref bucket_id) => write!(f, "B2ObjectStore({bucket_id})"),
+            None => f.write_str("B2ObjectStore(default)"),
+        }
+    }
+}
+
+fn not_found(location: &Path) -> ::object_store::Error {
+    ::object_store::Error::NotFound {
+        path: location.to_string(),
+        source: "file not found".into(),
+    }
+}
+
+fn into_error(e: B2Error, location: &Path) -> ::object_store::Error {
+    use ::object_store::Error;
+
+    let path = location.to_string();
+
+    match e {
+        B2Error::B2ErrorMessage(ref msg) => match msg.status {
+            404 => Error::NotFound {
+                path,
+                source: Box::new(e),
+            },
+            401 => Error::Unauthenticated {
+                path,
+                source: Box::new(e),
+            },
+            403 => Error::PermissionDenied {
+                path,
+                source: Box::new(e),
+            },
+            _ => Error::Generic {
+                store: STORE,
+                source: Box::new(e),
+            },
+        },
+        B2Error::MissingCapability(_) | B2Error::InvalidPrefix => Error::PermissionDenied {
+            path,
+            source: Box::new(e),
+        },
+        _ => Error::Generic {
+            store: STORE,
+            source: Box::new(e),
+        },
+    }
+}
+
+/// Converts put attributes into a content type and file info.
+fn attributes(attributes: &Attributes) -> Result<(Option<&str>, HashMap<SmolStr, SmolStr>)> {
+    let mut content_type = None;
+    let mut file_info = HashMap::new();
+
+    for (key, value) in attributes {
+        match key {
+            Attribute::ContentType => content_type = Some(value.as_ref()),
+            Attribute::Metadata(key) => {
+                _ = file_info.insert(SmolStr::from(key.as_ref()), SmolStr::from(value.as_ref()))
+            }
+            _ => {
+                return Err(::object_store::Error::NotSupported {
+                    source: format!("{key:?} attribute is not supported by B2").into(),
+                })
+            }
+        }
+    }
+
+    Ok((content_type, file_info))
+}
+
+fn object_meta(file: &B2FileInfo) -> ObjectMeta {
+    ObjectMeta {
+        location: Path::from(file.file_name.as_str()),
+        last_modified: chrono::DateTime::from_timestamp_millis(file.upload_timestamp as i64).unwrap_or_default(),
+        size: file.content_length as usize,
+        e_tag: Some(file.file_id.to_string()),
+        version: Some(file.file_id.to_string()),
+    }
+}
+
+/// Checks the conditions of a get request against the object metadata.
+pub(crate) fn check_preconditions(options: &GetOptions, meta: &ObjectMeta) -> Result<()> {
+    use ::object_store::Error;
+
+    let path = || meta.location.to_string();
+    let e_tag = meta.e_tag.as_deref().unwrap_or("*");
+    let matches = |tags: &str| tags.split(',').map(str::trim).any(|tag| tag == "*" || tag == e_tag);
+
+    if let Some(ref tags) = options.if_match {
+        if !matches(tags) {
+            return Err(Error::Precondition {
+                path: path(),
+                source: format!("{e_tag} does not match {tags}").into(),
+            });
+        }
+    } else if let Some(date) = options.if_unmodified_since {
+        if meta.last_modified > date {
+            return Err(Error::Precondition {
+                path: path(),
+                source: format!("{date} < {}", meta.last_modified).into(),
+            });
+        }
+    }
+
+    if let Some(ref tags) = options.if_none_match {
+        if matches(tags) {
+            return Err(Error::NotModified {
+                path: path(),
+                source: format!("{e_tag} matches {tags}").into(),
+            });
+        }
+    } else if let Some(date) = options.if_modified_since {
+        if meta.last_modified <= date {
+            return Err(Error::NotModified {
+                path: path(),
+                source: format!("{date} >= {}", meta.last_modified).into(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Resolves a requested range against the size of the object.
+pub(crate) fn resolve_range(range: Option<&GetRange>, size: usize, location: &Path) -> Result<Range<usize>> {
+    let range = match range {
+        None => return Ok(0..size),
+        Some(GetRange::Bounded(range)) => range.start..range.end.min(size),
+        Some(GetRange::Offset(offset)) => *offset..size,
+        Some(GetRange::Suffix(n)) => size.saturating_sub(*n)..size,
+    };
+
+    if range.start >= range.end && size > 0 {
+        return Err(::object_store::Error::Generic {
+            store: STORE,
+            source: format!("invalid range {range:?} for {location} of {size} bytes").into(),
+        });
+    }
+
+    Ok(range)
+}
+
+#[async_trait::async_trait]
+impl ObjectStore for B2ObjectStore {
+    /// Uploads the payload, in parts with [`LargeFileUpload`] if larger than the recommended part size.
+    ///
+    /// [`PutMode::Create`] is checked before uploading, so is not atomic. [`PutMode::Update`] is not supported.
+    async fn put_opts(&self, location: &Path, payload: PutPayload, opts: PutOptions) -> Result<PutResult> {
+        self.put_bytes(location, Bytes::from(payload), opts).await
+    }
+
+    async fn put_multipart_opts(
+        &self,
+        location: &Path,
+        opts: PutMultipartOpts,
+    ) -> Result<Box<dyn MultipartUpload>> {
+        let (content_type, file_info) = attributes(&opts.attributes)?;
+
+        let info = NewLargeFileInfo::builder()
+            .file_name(location.as_ref())
+            .content_type(content_type)
+            .file_info(&file_info)
+            .build();
+
+        let large =
+            self.client.start_large_file(self.bucket_id(), &info).await.map_err(|e| into_error(e, location))?;
+
+        Ok(Box::new(B2MultipartUpload {
+            client: self.client.clone(),
+            location: location.clone(),
+            file_id: large.info().file_id.clone(),
+            next_part_number: 1,
+            state: Arc::default(),
+        }))
+    }
+
+    /// Looks up the latest version of the file with [`Client::list_files`],
+    /// or the requested [`GetOptions::version`] with [`Client::get_file_info`], then downloads the range.
+    async fn get_opts(&self, location: &Path, options: GetOptions) -> Result<GetResult> {
+        let file = self.find(location, options.version.as_deref()).await?;
+
+        let meta = object_meta(&file);
+
+        check_preconditions(&options, &meta)?;
+
+        let range = resolve_range(options.range.as_ref(), meta.size, location)?;
+
+        if options.head || range.is_empty() {
+            return Ok(GetResult {
+                payload: GetResultPayload::Stream(stream::empty().boxed()),
+                meta,
+                range,
+                attributes: Attributes::new(),
+            });
+        }
+
+        let header = match options.range {
+            Some(_) => headers::Range::bytes(range.start as u64..range.end as u64).ok(),
+            None => None,
+        };
+
+        let download = self
+            .client
+            .download_file(DownloadFileBy::FileId(&file.file_id), header, None)
+            .await
+            .map_err(|e| into_error(e, location))?;
+
+        let mut attributes = Attributes::new();
+
+        if let Some(content_type) = file.content_type {
+            attributes.insert(Attribute::ContentType, content_type.to_string().into());
+        }
+
+        for (key, value) in file.file_info {
+            attributes.insert(Attribute::Metadata(key.to_string().into()), value.to_string().into());
+        }
+
+        let stream = download.bytes_stream().map_err(|e| ::object_store::Error::Generic {
+            store: STORE,
+            source: Box::new(e),
+        });
+
+        Ok(GetResult {
+            payload: GetResultPayload::Stream(stream.boxed()),
+            meta,
+            range,
+            attributes,
+        })
+    }
+
+    /// Looks up the latest version of the file with [`Client::list_files`].
+    async fn head(&self, location: &Path) -> Result<ObjectMeta> {
+        self.find(location, None).await.map(|file| object_meta(&file))
+    }
+
+    /// Deletes every version of the file, succeeding even if there are none.
+    async fn delete(&self, location: &Path) -> Result<()> {
+        let name = location.as_ref();
+
+        let (mut start_file_name, mut start_file_id) = (SmolStr::from(name), None::<SmolStr>);
+
+        loop {
+            let list = self
+                .client
+                .list_files(
+                    &ListFiles::builder()
+                        .all_versions(true)
+                        .bucket_id(self.bucket_id())
+                        .prefix(name)
+                        .start_file_name(start_file_name.as_str())
+                        .start_file_id(start_file_id.as_deref())
+                        .max_file_count(1000)
+                        .build(),
+                )
+                .await
+                .map_err(|e| into_error(e, location))?;
+
+            for file in list.files.iter().filter(|file| file.file_name == name) {
+                self.client
+                    .delete_file(&file.file_id, &file.file_name, false)
+                    .await
+                    .map_err(|e| into_error(e, location))?;
+            }
+
+            // versions are sorted by name, so there are no more once the next name differs
+            match list.next_file_name {
+                Some(next) if next == name => (start_file_name, start_file_id) = (next, list.next_file_id),
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, Result<ObjectMeta>> {
+        let prefix = prefix.map(|prefix| format!("{prefix}{}", DELIMITER)).filter(|prefix| prefix != "/");
+
+        let pages = stream::try_unfold(Some(None::<SmolStr>), move |start| {
+            let prefix = prefix.clone();
+
+            async move {
+                let Some(start) = start else {
+                    return Ok::<_, ::object_store::Error>(None);
+                };
+
+                let list = self
+                    .client
+                    .list_files(
+                        &ListFiles::builder()
+                            .bucket_id(self.bucket_id())
+                            .prefix(prefix.as_deref())
+                            .start_file_name(start.as_deref())
+                            .max_file_count(1000)
+                            .build(),
+                    )
+                    .await
+                    .map_err(|e| into_error(e, &Path::from(prefix.unwrap_or_default())))?;
+
+                let objects: Vec<_> = list
+                    .files
+                    .iter()
+                    .filter(|file| matches!(file.action, None | Some(B2FileAction::Uploaded)))
+                    .map(|file| Ok(object_meta(file)))
+                    .collect();
+
+                Ok(Some((stream::iter(objects), list.next_file_name.map(Some))))
+            }
+        });
+
+        pages.try_flatten().boxed()
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> Result<ListResult> {
+        let prefix = prefix.map(|prefix| format!("{prefix}{}", DELIMITER)).filter(|prefix| prefix != "/");
+
+        let mut result = ListResult {
+            common_prefixes: Vec::new(),
+            objects: Vec::new(),
+        };
+
+        let mut start = None::<SmolStr>;
+
+        loop {
+            let list = self
+                .client
+                .list_files(
+                    &ListFiles::builder()
+                        .bucket_id(self.bucket_id())
+                        .prefix(prefix.as_deref())
+                        .delimiter(DELIMITER)
+                        .start_file_name(start.as_deref())
+                        .max_file_count(1000)
+                        .build(),
+                )
+                .await
+                .map_err(|e| into_error(e, &Path::from(prefix.clone().unwrap_or_default())))?;
+
+            for file in &list.files {
+                match file.action {
+                    Some(B2FileAction::Folder) => {
+                        result.common_prefixes.push(Path::from(file.file_name.trim_end_matches(DELIMITER)))
+                    }
+                    None | Some(B2FileAction::Uploaded) => result.objects.push(object_meta(file)),
+                    _ => {}
+                }
+            }
+
+            match list.next_file_name {
+                Some(next) => start = Some(next),
+                None => return Ok(result),
+            }
+        }
+    }
+
+    /// Copies the latest version of the file on the server side with `b2_copy_file`,
+    /// or in parts with `b2_copy_part` for files larger than 5GB.
+    async fn copy(&self, from: &Path, to: &Path) -> Result<()> {
+        let file = self.find(from, None).await?;
+
+        self.client
+            .copy_whole_file(
+                &file,
+                self.bucket_id(),
+                to.as_ref(),
+                None,
+                &sse::ServerSideEncryption::Default,
+            )
+            .await
+            .map(|_| ())
+            .map_err(|e| into_error(e, to))
+    }
+
+    /// Not supported, as B2 has no atomic conditional copy.
+    async fn copy_if_not_exists(&self, _from: &Path, _to: &Path) -> Result<()> {
+        Err(::object_store::Error::NotSupported {
+            source: "B2 does not support atomic conditional copies".into(),
+        })
+    }
+}
+
+#[derive(Default)]
+struct MultipartState {
+    parts: Vec<B2PartInfo>,
+    urls: Vec<UploadPartUrl>,
+}
+
+/// A multipart upload to B2, using [`LargeFileUpload`].
+///
+/// Each part is uploaded with its own upload part URL, which are reused for later parts.
+struct B2MultipartUpload {
+    client: Client,
+    location: Path,
+    file_id: SmolStr,
+    next_part_number: u32,
+    state: Arc<Mutex<MultipartState>>,
+}
+
+impl fmt::Debug for B2MultipartUpload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("B2MultipartUpload")
+            .field("location", &self.location)
+            .field("file_id", &self.file_id)
+            .field("next_part_number", &self.next_part_number)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait::async_trait]
+impl MultipartUpload for B2MultipartUpload {
+    fn put_part(&mut self, data: PutPayload) -> UploadPart {
+        let part_number = self.next_part_number;
+        self.next_part_number += 1;
+
+        let large = LargeFileUpload::existing(&self.client, self.file_id.clone());
+        let state = self.state.clone();
+        let location = self.location.clone();
+
+        Box::pin(async move {
+            let part = Bytes::from(data);
+
+            let res = async {
+                let url = state.lock().unwrap_or_else(|e| e.into_inner()).urls.pop();
+
+                let mut url = match url {
+                    Some(url) => url,
+                    None => large.get_upload_part_url().await?,
+                };
+
+                let info = NewPartInfo::builder()
+                    .part_number(std::num::NonZeroU32::new(part_number).ok_or(B2Error::Unknown)?)
+                    .content_length(part.len() as u64)
+                    .build();
+
+                let part = large.upload_part_bytes(&mut url, &info, part).await?;
+
+                let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+
+                state.parts.push(part);
+                state.urls.push(url);
+
+                Ok(())
+            };
+
+            res.await.map_err(|e| into_error(e, &location))
+        })
+    }
+
+    async fn complete(&mut self) -> Result<PutResult> {
+        let mut parts = std::mem::take(&mut self.state.lock().unwrap_or_else(|e| e.into_inner()).parts);
+
+        parts.sort_by_key(|part| part.part_number);
+
+        let file = LargeFileUpload::existing(&self.client, self.file_id.clone())
+            .finish(&parts)
+            .await
+            .map_err(|e| into_error(e, &self.location))?;
+
+        Ok(PutResult {
+            e_tag: Some(file.file_id.to_string()),
+            version: Some(file.file_id.to_string()),
+        })
+    }
+
+    async fn abort(&mut self) -> Result<()> {
+        LargeFileUpload::existing(&self.client, self.file_id.clone())
+            .cancel()
+            .await
+            .map(|_| ())
+            .map_err(|e| into_error(e, &self.location))
+    }
+}
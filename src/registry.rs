@@ -0,0 +1,206 @@
+//! Clients for multiple accounts or credentials, routed by name or bucket.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use smol_str::SmolStr;
+use tokio::sync::OnceCell;
+
+use crate::transport::{HttpTransport, ReqwestTransport};
+use crate::*;
+
+struct Account {
+    builder: ClientBuilder,
+    client: OnceCell<Client>,
+    last_error: Mutex<Option<String>>,
+}
+
+/// A registry of [`Client`]s for several accounts or application keys, each authorized lazily on first use
+/// and sharing one HTTP connection pool.
+///
+/// Buckets are routed to accounts with [`ClientRegistry::route_bucket`]. Keys restricted to a single bucket
+/// are also routed to automatically, by both bucket ID and name, once authorized.
+///
+/// # Example
+///
+/// ```ignore
+/// let registry = ClientRegistry::new()
+///     .account("us-west", ClientBuilder::new(&us_key_id, &us_key))
+///     .account("eu-central", ClientBuilder::new(&eu_key_id, &eu_key))
+///     .route_bucket("tenant-assets-eu", "eu-central");
+///
+/// let client = registry.for_bucket("tenant-assets-eu").await?;
+/// ```
+pub struct ClientRegistry {
+    transport: Arc<dyn HttpTransport>,
+    accounts: HashMap<SmolStr, Account>,
+    routes: HashMap<SmolStr, SmolStr>,
+}
+
+/// The health of an account in a [`ClientRegistry`], see [`ClientRegistry::health`].
+#[derive(Debug, Clone)]
+pub struct AccountHealth {
+    /// The name of the account in the registry.
+    pub name: SmolStr,
+
+    /// Whether the account has been authorized successfully.
+    ///
+    /// Accounts are only authorized when first used.
+    pub authorized: bool,
+
+    /// When the account was last authorized, in milliseconds since the Unix epoch.
+    pub authorized_at: Option<u64>,
+
+    /// How many times the account has been reauthorized since first authorized.
+    pub reauthorizations: u64,
+
+    /// When the application key expires, in milliseconds since the Unix epoch, if it does.
+    pub key_expiration: Option<u64>,
+
+    /// The error from the last failed attempt to authorize the account, if any.
+    pub last_error: Option<String>,
+}
+
+impl Default for ClientRegistry {
+    fn default() -> Self {
+        ClientRegistry::new()
+    }
+}
+
+impl ClientRegistry {
+    /// Creates an empty registry with a default HTTP client.
+    pub fn new() -> ClientRegistry {
+        let http = reqwest::ClientBuilder::new().https_only(true).build().expect("Unable to build HTTP client");
+
+        ClientRegistry::with_http_client(http)
+    }
+
+    /// Creates an empty registry using the given HTTP client for every account.
+    ///
+    /// [`ClientBuilder::user_agent`] and [`ClientBuilder::configure_http`] are ignored for accounts in the registry,
+    /// set them on the HTTP client instead. Accounts with their own [`ClientBuilder::http_client`] use that instead.
+    pub fn with_http_client(http: reqwest::Client) -> ClientRegistry {
+        ClientRegistry::with_transport(ReqwestTransport::new(http))
+    }
+
+    /// Creates an empty registry sending requests for every account through the given transport.
+    ///
+    /// Accounts with their own [`ClientBuilder::transport`] or [`ClientBuilder::http_client`] use that instead.
+    pub fn with_transport(transport: impl HttpTransport) -> ClientRegistry {
+        ClientRegistry {
+            transport: Arc::new(transport),
+            accounts: HashMap::new(),
+            routes: HashMap::new(),
+        }
+    }
+
+    /// Adds an account with the given name, replacing any existing account with the same name.
+    pub fn account(mut self, name: impl Into<SmolStr>, builder: ClientBuilder) -> Self {
+        self.accounts.insert(
+            name.into(),
+            Account {
+                builder,
+                client: OnceCell::new(),
+                last_error: Mutex::new(None),
+            },
+        );
+        self
+    }
+
+    /// Routes a bucket, by ID or name, to the account with the given name.
+    pub fn route_bucket(mut self, bucket: impl Into<SmolStr>, account: impl Into<SmolStr>) -> Self {
+        self.routes.insert(bucket.into(), account.into());
+        self
+    }
+
+    /// Gets the client for the account with the given name, authorizing it if needed.
+    pub async fn get(&self, name: &str) -> Result<Client, B2Error> {
+        let account = self.accounts.get(name).ok_or_else(|| B2Error::UnknownAccount(SmolStr::from(name)))?;
+
+        let res = account
+            .client
+            .get_or_try_init(|| {
+                let mut builder = account.builder.clone();
+                let transport = builder.take_transport().unwrap_or_else(|| self.transport.clone());
+
+                builder.authorize_with(transport)
+            })
+            .await;
+
+        *account.last_error.lock().unwrap_or_else(|e| e.into_inner()) =
+            res.as_ref().err().map(ToString::to_string);
+
+        res.cloned()
+    }
+
+    /// Gets the client for the account a bucket is routed to, by bucket ID or name.
+    ///
+    /// If the bucket has no explicit route, accounts that are already authorized
+    /// with a key restricted to that bucket are used.
+    pub async fn for_bucket(&self, bucket: &str) -> Result<Client, B2Error> {
+        if let Some(account) = self.routes.get(bucket) {
+            return self.get(account).await;
+        }
+
+        for account in self.accounts.values() {
+            let Some(client) = account.client.get() else { continue };
+
+            let state = client.state.read().await;
+            let storage = &state.account.api.storage;
+
+            if storage.bucket_id.as_deref() == Some(bucket) || storage.bucket_name.as_deref() == Some(bucket) {
+                return Ok(client.clone());
+            }
+        }
+
+        Err(B2Error::UnknownBucket(SmolStr::from(bucket)))
+    }
+
+    /// Authorizes every account that has not been authorized yet, so that buckets of restricted keys can be
+    /// routed to, returning the accounts that failed to authorize.
+    pub async fn authorize_all(&self) -> Vec<(SmolStr, B2Error)> {
+        let mut failed = Vec::new();
+
+        for name in self.accounts.keys() {
+            if let Err(e) = self.get(name).await {
+                failed.push((name.clone(), e));
+            }
+        }
+
+        failed
+    }
+
+    /// Returns the names of every account in the registry.
+    pub fn accounts(&self) -> impl Iterator<Item = &str> {
+        self.accounts.keys().map(SmolStr::as_str)
+    }
+
+    /// Returns the health of every account in the registry, without authorizing any accounts.
+    pub async fn health(&self) -> Vec<AccountHealth> {
+        let mut health = Vec::with_capacity(self.accounts.len());
+
+        for (name, account) in &self.accounts {
+            let mut h = AccountHealth {
+                name: name.clone(),
+                authorized: false,
+                authorized_at: None,
+                reauthorizations: 0,
+                key_expiration: None,
+                last_error: account.last_error.lock().unwrap_or_else(|e| e.into_inner()).clone(),
+            };
+
+            if let Some(client) = account.client.get() {
+                let state = client.state.read().await;
+
+                h.authorized = true;
+                h.authorized_at = Some(state.authorized_at);
+                h.reauthorizations = state.generation;
+                h.key_expiration = state.account.expiration;
+            }
+
+            health.push(h);
+        }
+
+        health
+    }
+}
@@ -0,0 +1,347 @@
+//! Recording and replaying B2 interactions, for testing without the network.
+//!
+//! A [`RecordingTransport`] wraps another transport in a real session and keeps every request and response,
+//! with authorization headers, tokens, application keys and SSE-C keys redacted, to be saved as a JSON fixture.
+//! A [`ReplayTransport`] then serves those responses in tests, failing with [`ReplayError`] on any request
+//! that was not recorded.
+//!
+//! # Example
+//!
+//! ```ignore
+//! // record once against B2
+//! let recorder = Arc::new(RecordingTransport::new(ReqwestTransport::new(reqwest::Client::new())));
+//! let client = ClientBuilder::new(&key_id, &key).transport(recorder.clone()).authorize().await?;
+//! run_scenario(&client).await?;
+//! recorder.save("tests/fixtures/scenario.json")?;
+//!
+//! // then replay in tests
+//! let replay = Arc::new(ReplayTransport::load("tests/fixtures/scenario.json")?);
+//! let client = ClientBuilder::new("id", "key").transport(replay.clone()).authorize().await?;
+//! run_scenario(&client).await?;
+//! replay.finish()?;
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+use bytes::Bytes;
+use futures_util::future::BoxFuture;
+use http_body_util::{BodyExt, Full};
+use reqwest::header::{HeaderMap, AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE};
+
+use crate::error::ReplayError;
+use crate::transport::{body_error, HttpTransport, ResponseBody};
+use crate::B2Error;
+
+/// Replaces redacted values in fixtures.
+pub const REDACTED: &str = "REDACTED";
+
+/// JSON fields redacted from request and response bodies.
+const REDACTED_FIELDS: &[&str] = &["authorizationToken", "applicationKey", "customerKey"];
+
+/// Headers redacted from requests and responses, along with `Authorization`.
+const REDACTED_HEADERS: &[&str] = &["x-bz-server-side-encryption-customer-key"];
+
+/// A request and the response it received.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interaction {
+    /// The recorded request.
+    pub request: RecordedRequest,
+
+    /// The recorded response.
+    pub response: RecordedResponse,
+}
+
+/// A recorded request. Only the method, URL and body are matched when replaying.
+///
+/// Hand-written fixtures can leave out the body to match any body.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    /// The HTTP method.
+    pub method: String,
+
+    /// The full URL, including the query string.
+    pub url: String,
+
+    /// The request headers, with the `Authorization` header and SSE-C keys redacted.
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+
+    /// The request body, if not empty.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<RecordedBody>,
+}
+
+/// A recorded response.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    /// The HTTP status code.
+    pub status: u16,
+
+    /// The response headers.
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+
+    /// The response body, if not empty.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<RecordedBody>,
+}
+
+/// A recorded request or response body.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordedBody {
+    /// A JSON body of an API call, with tokens, application keys and SSE-C keys redacted.
+    Json(serde_json::Value),
+
+    /// Any other UTF-8 body.
+    Text(String),
+
+    /// A binary body, as lowercase hex.
+    Hex(String),
+}
+
+impl RecordedBody {
+    fn new(headers: &HeaderMap, bytes: &[u8]) -> Option<RecordedBody> {
+        if bytes.is_empty() {
+            return None;
+        }
+
+        if is_api_json(headers) {
+            if let Ok(mut json) = serde_json::from_slice::<serde_json::Value>(bytes) {
+                redact(&mut json);
+
+                return Some(RecordedBody::Json(json));
+            }
+        }
+
+        Some(match std::str::from_utf8(bytes) {
+            Ok(text) => RecordedBody::Text(text.to_owned()),
+            Err(_) => RecordedBody::Hex(hex::encode(bytes)),
+        })
+    }
+
+    fn to_bytes(&self) -> Bytes {
+        match self {
+            RecordedBody::Json(json) => Bytes::from(json.to_string()),
+            RecordedBody::Text(text) => Bytes::from(text.clone()),
+            RecordedBody::Hex(hex) => Bytes::from(hex::decode(hex).unwrap_or_default()),
+        }
+    }
+}
+
+/// Whether a body is JSON from an API call, rather than the contents of an uploaded or downloaded file,
+/// which always have `X-Bz-*` headers and must be replayed byte-for-byte.
+fn is_api_json(headers: &HeaderMap) -> bool {
+    let json = headers.get(CONTENT_TYPE).is_some_and(|ct| ct.as_bytes().starts_with(b"application/json"));
+
+    json && !headers.keys().any(|name| name.as_str().starts_with("x-bz-"))
+}
+
+fn redact(json: &mut serde_json::Value) {
+    match json {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                match REDACTED_FIELDS.contains(&key.as_str()) && value.is_string() {
+                    true => *value = serde_json::Value::String(REDACTED.to_owned()),
+                    false => redact(value),
+                }
+            }
+        }
+        serde_json::Value::Array(values) => values.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+fn record_headers(headers: &HeaderMap) -> BTreeMap<String, String> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = match *name == AUTHORIZATION || REDACTED_HEADERS.contains(&name.as_str()) {
+                true => REDACTED.to_owned(),
+                false => String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            };
+
+            (name.as_str().to_owned(), value)
+        })
+        .collect()
+}
+
+/// Buffers a request, returning it with a replayable body along with its recorded form.
+async fn record_request(
+    request: http::Request<reqwest::Body>,
+) -> Result<(http::Request<reqwest::Body>, RecordedRequest), B2Error> {
+    let (parts, body) = request.into_parts();
+    let body = body.collect().await.map_err(|e| body_error(e.into()))?.to_bytes();
+
+    let recorded = RecordedRequest {
+        method: parts.method.to_string(),
+        url: parts.uri.to_string(),
+        headers: record_headers(&parts.headers),
+        body: RecordedBody::new(&parts.headers, &body),
+    };
+
+    Ok((http::Request::from_parts(parts, reqwest::Body::from(body)), recorded))
+}
+
+fn full_body(body: Bytes) -> ResponseBody {
+    Full::new(body).map_err(|never| match never {}).boxed()
+}
+
+/// An [`HttpTransport`] that records every interaction made through another transport.
+///
+/// Request and response bodies are buffered in memory, so this should not be used for large uploads or downloads.
+pub struct RecordingTransport<T> {
+    inner: T,
+    interactions: Mutex<Vec<Interaction>>,
+}
+
+impl<T: HttpTransport> RecordingTransport<T> {
+    /// Creates a transport recording interactions made through `inner`.
+    pub fn new(inner: T) -> Self {
+        RecordingTransport {
+            inner,
+            interactions: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns the interactions recorded so far, in the order the responses were received.
+    pub fn interactions(&self) -> Vec<Interaction> {
+        self.interactions.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Serializes the recorded interactions as a JSON fixture.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.interactions()).expect("Unable to serialize interactions")
+    }
+
+    /// Writes the recorded interactions to a JSON fixture file.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), B2Error> {
+        Ok(std::fs::write(path, self.to_json())?)
+    }
+}
+
+impl<T: HttpTransport> HttpTransport for RecordingTransport<T> {
+    fn send(
+        &self,
+        request: http::Request<reqwest::Body>,
+    ) -> BoxFuture<'_, Result<http::Response<ResponseBody>, B2Error>> {
+        Box::pin(async move {
+            let (request, recorded) = record_request(request).await?;
+
+            let resp = self.inner.send(request).await?;
+
+            let (parts, body) = resp.into_parts();
+            let body = body.collect().await.map_err(body_error)?.to_bytes();
+
+            let interaction = Interaction {
+                request: recorded,
+                response: RecordedResponse {
+                    status: parts.status.as_u16(),
+                    headers: record_headers(&parts.headers),
+                    body: RecordedBody::new(&parts.headers, &body),
+                },
+            };
+
+            self.interactions.lock().unwrap_or_else(|e| e.into_inner()).push(interaction);
+
+            Ok(http::Response::from_parts(parts, full_body(body)))
+        })
+    }
+}
+
+/// An [`HttpTransport`] that replays recorded interactions instead of making requests.
+///
+/// Each request is matched by method, URL and body, if recorded, against the interactions that have not been
+/// replayed yet, in recorded order, so identical requests receive their responses in the order they were recorded.
+/// Requests that match no remaining interaction fail with [`ReplayError::UnexpectedRequest`].
+pub struct ReplayTransport {
+    remaining: Mutex<Vec<Interaction>>,
+}
+
+impl ReplayTransport {
+    /// Creates a transport replaying the given interactions.
+    pub fn new(interactions: Vec<Interaction>) -> Self {
+        ReplayTransport {
+            remaining: Mutex::new(interactions),
+        }
+    }
+
+    /// Creates a transport replaying the interactions in a JSON fixture.
+    pub fn from_json(json: &str) -> Result<Self, B2Error> {
+        Ok(ReplayTransport::new(serde_json::from_str(json)?))
+    }
+
+    /// Creates a transport replaying the interactions in a JSON fixture file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, B2Error> {
+        ReplayTransport::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    /// Returns how many recorded interactions have not been replayed yet.
+    pub fn remaining(&self) -> usize {
+        self.remaining.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    /// Checks that every recorded interaction has been replayed.
+    pub fn finish(&self) -> Result<(), ReplayError> {
+        let remaining = self.remaining.lock().unwrap_or_else(|e| e.into_inner());
+
+        match remaining.first() {
+            Some(next) => Err(ReplayError::Unused {
+                count: remaining.len(),
+                next: format!("{} {}", next.request.method, next.request.url),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+impl HttpTransport for ReplayTransport {
+    fn send(
+        &self,
+        request: http::Request<reqwest::Body>,
+    ) -> BoxFuture<'_, Result<http::Response<ResponseBody>, B2Error>> {
+        Box::pin(async move {
+            let (_, request) = record_request(request).await?;
+
+            let interaction = {
+                let mut remaining = self.remaining.lock().unwrap_or_else(|e| e.into_inner());
+
+                let found = remaining.iter().position(|i| {
+                    i.request.method == request.method
+                        && i.request.url == request.url
+                        && (i.request.body.is_none() || i.request.body == request.body)
+                });
+
+                match found {
+                    Some(idx) => remaining.remove(idx),
+                    None => {
+                        return Err(B2Error::from(ReplayError::UnexpectedRequest {
+                            method: request.method,
+                            url: request.url,
+                            next: remaining.first().map(|i| format!("{} {}", i.request.method, i.request.url)),
+                        }))
+                    }
+                }
+            };
+
+            let mut resp = http::Response::builder().status(interaction.response.status);
+
+            for (name, value) in &interaction.response.headers {
+                resp = resp.header(name, value);
+            }
+
+            let body = interaction.response.body.as_ref().map(RecordedBody::to_bytes).unwrap_or_default();
+
+            // JSON bodies are not replayed byte-for-byte
+            if let Some(headers) = resp.headers_mut() {
+                if headers.contains_key(CONTENT_LENGTH) {
+                    headers.insert(CONTENT_LENGTH, body.len().into());
+                }
+            }
+
+            resp.body(full_body(body)).map_err(|e| B2Error::Transport(e.into()))
+        })
+    }
+}
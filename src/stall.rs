@@ -0,0 +1,88 @@
+//! Stall timeouts for upload bodies.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt};
+use http_body_util::BodyDataStream;
+
+use crate::transport::RequestBuilder;
+use crate::B2Error;
+
+/// The largest chunk passed on at once, so progress follows the writes to the connection
+/// even when the whole body is a single buffer.
+const MAX_CHUNK_SIZE: usize = 64 * 1024;
+
+/// A stream of bytes that counts the chunks read from it, as a measure of upload progress.
+///
+/// Chunks are split into slices of at most [`MAX_CHUNK_SIZE`] bytes.
+struct ProgressStream {
+    inner: BodyDataStream<reqwest::Body>,
+    pending: Bytes,
+    progress: Arc<AtomicU64>,
+}
+
+impl Stream for ProgressStream {
+    type Item = Result<Bytes, reqwest::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if !self.pending.is_empty() {
+                let n = self.pending.len().min(MAX_CHUNK_SIZE);
+                let chunk = self.pending.split_to(n);
+
+                self.progress.fetch_add(1, Ordering::Relaxed);
+
+                return Poll::Ready(Some(Ok(chunk)));
+            }
+
+            match self.inner.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(chunk))) => self.pending = chunk,
+                res => return res,
+            }
+        }
+    }
+}
+
+/// Runs a request with `f`, failing with [`B2Error::Stalled`] if no part of the body is sent for `stall`,
+/// or the response takes longer than `stall` once the body has been sent.
+///
+/// Progress is checked every `stall`, so a stalled request fails after at most twice that.
+pub(crate) async fn watch<F, R, T>(req: RequestBuilder, stall: Duration, f: F) -> Result<T, B2Error>
+where
+    F: FnOnce(RequestBuilder) -> R,
+    R: Future<Output = Result<T, B2Error>>,
+{
+    let progress = Arc::new(AtomicU64::new(0));
+
+    let req = req.map_body(|body| {
+        reqwest::Body::wrap_stream(ProgressStream {
+            inner: BodyDataStream::new(body),
+            pending: Bytes::new(),
+            progress: progress.clone(),
+        })
+    });
+
+    let mut res = std::pin::pin!(f(req));
+    let mut seen = 0;
+
+    loop {
+        match tokio::time::timeout(stall, &mut res).await {
+            Ok(res) => return res,
+            Err(_) => {
+                let current = progress.load(Ordering::Relaxed);
+
+                if current == seen {
+                    return Err(B2Error::Stalled(stall));
+                }
+
+                seen = current;
+            }
+        }
+    }
+}
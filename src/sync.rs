@@ -0,0 +1,672 @@
+//! Synchronizing a local directory with a prefix in a bucket.
+//!
+//! Local files are compared with the remote files by size and modification time,
+//! where the remote modification time is taken from the `src_last_modified_millis`
+//! file info if present, otherwise the upload timestamp. Optionally, the SHA1 hashes
+//! of the file contents can be compared instead of modification times.
+//!
+//! A [`SyncPlan`] is computed first with [`Client::sync_plan`], which can then be inspected
+//! and executed with [`Client::sync_execute`], or both at once with [`Client::sync`].
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use futures_util::stream::{self, StreamExt};
+use sha1::{Digest, Sha1};
+use smol_str::SmolStr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::fs::{modified_millis, walk_dir, LocalFile, PathFilter, SRC_LAST_MODIFIED_MILLIS};
+use crate::models::{B2FileAction, B2FileInfo};
+use crate::pool::Pool;
+use crate::*;
+
+/// Which way files are copied during a sync.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncDirection {
+    /// Make the remote prefix match the local directory.
+    Upload,
+
+    /// Make the local directory match the remote prefix.
+    Download,
+
+    /// Copy missing files both ways, resolving files that differ with the [`ConflictPolicy`].
+    ///
+    /// Nothing is ever deleted in this mode, as it's not possible to know if a
+    /// file missing on one side was deleted there or added on the other side.
+    Bidirectional,
+}
+
+/// How to resolve a file that exists both locally and remotely, but differs.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Keep whichever file was modified most recently. Ties are resolved in favor of the local file.
+    ///
+    /// When only syncing in one direction, files are not overwritten by older files.
+    #[default]
+    KeepNewest,
+
+    /// Always keep the local file, overwriting the remote file when uploading,
+    /// and never overwriting the local file when downloading.
+    KeepLocal,
+}
+
+/// How to delete remote files that no longer exist locally, when [`SyncOptions::delete`] is set.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemoteDelete {
+    /// Hide the file, keeping previous versions.
+    #[default]
+    Hide,
+
+    /// Delete all versions of the file.
+    AllVersions,
+}
+
+/// Options for syncing a directory with [`Client::sync`].
+///
+/// See the documentation for [`SyncOptions::builder`] for more information.
+#[derive(Debug, typed_builder::TypedBuilder)]
+#[builder(doc)]
+pub struct SyncOptions<'a> {
+    /// Which way files are copied.
+    pub direction: SyncDirection,
+
+    /// The ID of the bucket to sync with. If `None`, the client's default bucket will be used.
+    #[builder(default, setter(into))]
+    pub bucket_id: Option<&'a str>,
+
+    /// How to resolve files that differ.
+    #[builder(default)]
+    pub policy: ConflictPolicy,
+
+    /// If `true`, delete files from the destination that do not exist in the source,
+    /// like `rsync --delete`. Ignored for [`SyncDirection::Bidirectional`].
+    #[builder(default)]
+    pub delete: bool,
+
+    /// How to delete remote files when uploading with [`delete`](SyncOptions::delete).
+    #[builder(default)]
+    pub remote_delete: RemoteDelete,
+
+    /// If `true`, files of equal size are compared by SHA1 hash instead of modification time,
+    /// which requires reading every such local file.
+    ///
+    /// Remote files without a known SHA1 hash fall back to comparing modification times.
+    #[builder(default)]
+    pub compare_sha1: bool,
+
+    /// If `true`, [`Client::sync`] only computes the plan without executing it.
+    #[builder(default)]
+    pub dry_run: bool,
+
+    /// Glob patterns for files to include, matched against the path relative to the local root
+    /// or remote prefix, using `/` as the separator.
+    ///
+    /// If empty, all files are included.
+    #[builder(default, setter(into))]
+    pub include: Vec<&'a str>,
+
+    /// Glob patterns for files to exclude, matched against the path relative to the local root
+    /// or remote prefix, using `/` as the separator.
+    ///
+    /// Exclusions take precedence over inclusions. Excluded files are never deleted.
+    #[builder(default, setter(into))]
+    pub exclude: Vec<&'a str>,
+
+    /// The maximum number of actions to execute at once.
+    ///
+    /// If set to 0, the default of 4 actions will be used.
+    #[builder(default)]
+    pub max_simultaneous_files: u8,
+
+    /// The maximum number of connections to use for each large file,
+    /// see [`NewFileFromPath::max_simultaneous_uploads`].
+    #[builder(default)]
+    pub max_simultaneous_uploads: u8,
+
+    /// The MIME type to use for uploaded files.
+    ///
+    /// Use `b2/x-auto` to have B2 determine the content type from each file's extension.
+    #[builder(default, setter(into))]
+    pub content_type: Option<&'a str>,
+
+    /// The server-side encryption to use for uploaded files.
+    ///
+    /// If using SSE-C, the same key is used to download files.
+    #[builder(default)]
+    pub encryption: sse::ServerSideEncryption,
+}
+
+/// A single step of a [`SyncPlan`].
+#[derive(Debug)]
+pub enum SyncAction {
+    /// Upload a local file, creating a new version of the remote file.
+    Upload {
+        /// The local path of the file.
+        path: PathBuf,
+
+        /// The name of the remote file.
+        file_name: SmolStr,
+    },
+
+    /// Download a remote file, overwriting any local file.
+    Download {
+        /// The remote file to download.
+        file: Box<B2FileInfo>,
+
+        /// The local path of the file.
+        path: PathBuf,
+    },
+
+    /// Hide a remote file.
+    HideRemote {
+        /// The name of the remote file.
+        file_name: SmolStr,
+    },
+
+    /// Delete all versions of a remote file.
+    DeleteRemote {
+        /// The name of the remote file.
+        file_name: SmolStr,
+
+        /// The IDs of every version of the file.
+        file_ids: Vec<SmolStr>,
+    },
+
+    /// Delete a local file.
+    DeleteLocal {
+        /// The local path of the file.
+        path: PathBuf,
+    },
+}
+
+/// The actions needed to sync a directory, computed by [`Client::sync_plan`].
+#[derive(Default, Debug)]
+pub struct SyncPlan {
+    /// The actions to execute, in no particular order.
+    pub actions: Vec<SyncAction>,
+
+    /// Local paths that could not be read, and the error that occurred.
+    ///
+    /// If any local paths could not be read, remote files are not deleted,
+    /// as they may only appear to be missing locally.
+    pub failed: Vec<(PathBuf, B2Error)>,
+}
+
+/// The results of executing a [`SyncPlan`].
+#[derive(Default, Debug)]
+pub struct SyncReport {
+    /// Actions that completed successfully.
+    pub completed: Vec<SyncAction>,
+
+    /// Actions that were planned but not executed, because of [`SyncOptions::dry_run`].
+    pub planned: Vec<SyncAction>,
+
+    /// Local paths or actions that failed, and the error that occurred.
+    pub failed: Vec<(SyncFailure, B2Error)>,
+}
+
+/// What failed during a sync, see [`SyncReport::failed`].
+#[derive(Debug)]
+pub enum SyncFailure {
+    /// A local path could not be read while planning.
+    Path(PathBuf),
+
+    /// An action could not be executed.
+    Action(SyncAction),
+}
+
+impl SyncReport {
+    /// Returns `true` if nothing failed.
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+/// A remote file under the prefix, with all of its versions if listed.
+struct RemoteFile {
+    /// The most recent version, or `None` if the file is hidden.
+    latest: Option<B2FileInfo>,
+
+    /// The IDs of every version of the file.
+    file_ids: Vec<SmolStr>,
+}
+
+/// What to do with a file found on one or both sides.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Decision {
+    Nothing,
+    Upload,
+    Download,
+    DeleteRemote,
+    DeleteLocal,
+}
+
+/// Summary of a file used to decide what to do with it.
+#[derive(Debug, Clone, Copy)]
+struct Side {
+    modified: u64,
+}
+
+fn decide(options: &SyncOptions, local: Option<Side>, remote: Option<Side>, same: bool) -> Decision {
+    use SyncDirection::*;
+
+    let delete = options.delete && options.direction != Bidirectional;
+
+    match (options.direction, local, remote) {
+        (_, None, None) => Decision::Nothing,
+        (Download, Some(_), None) if delete => Decision::DeleteLocal,
+        (Upload, None, Some(_)) if delete => Decision::DeleteRemote,
+        (Upload | Bidirectional, Some(_), None) => Decision::Upload,
+        (Download | Bidirectional, None, Some(_)) => Decision::Download,
+        (_, None, Some(_)) | (_, Some(_), None) => Decision::Nothing,
+        (_, Some(_), Some(_)) if same => Decision::Nothing,
+        (direction, Some(local), Some(remote)) => {
+            let keep_local = match options.policy {
+                ConflictPolicy::KeepLocal => true,
+                ConflictPolicy::KeepNewest => local.modified >= remote.modified,
+            };
+
+            match (direction, keep_local) {
+                (Upload | Bidirectional, true) => Decision::Upload,
+                (Download | Bidirectional, false) => Decision::Download,
+                _ => Decision::Nothing,
+            }
+        }
+    }
+}
+
+/// Gets the modification time of a remote file in milliseconds since the Unix epoch,
+/// from the `src_last_modified_millis` file info, falling back to the upload timestamp.
+fn remote_modified(file: &B2FileInfo) -> u64 {
+    file.file_info
+        .get(SRC_LAST_MODIFIED_MILLIS)
+        .and_then(|millis| millis.parse().ok())
+        .unwrap_or(file.upload_timestamp)
+}
+
+async fn hash_file(path: &Path) -> Result<String, B2Error> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut buf = vec![0; 64 * 1024];
+    let mut sha1 = Sha1::new();
+
+    loop {
+        match file.read(&mut buf).await? {
+            0 => break,
+            n => sha1.update(&buf[..n]),
+        }
+    }
+
+    Ok(hex::encode(sha1.finalize()))
+}
+
+/// Converts a remote file name to a relative local path, if it is safe to write to.
+pub(crate) fn local_relative(file_name: &str, remote_prefix: &str) -> Option<String> {
+    let relative = file_name.strip_prefix(remote_prefix)?;
+
+    let safe = !relative.is_empty()
+        && !relative.starts_with('/')
+        && relative.split('/').all(|part| !matches!(part, "" | "." | "..") && !part.contains('\\'));
+
+    safe.then(|| relative.to_owned())
+}
+
+impl Client {
+    /// Lists every file under `prefix`, including all versions if `all_versions` is `true`.
+    async fn list_all_files(
+        &self,
+        bucket_id: Option<&str>,
+        prefix: &str,
+        all_versions: bool,
+    ) -> Result<Vec<B2FileInfo>, B2Error> {
+        let mut files = Vec::new();
+        let (mut start_file_name, mut start_file_id) = (None::<SmolStr>, None::<SmolStr>);
+
+        loop {
+            let list = self
+                .list_files(
+                    &ListFiles::builder()
+                        .all_versions(all_versions)
+                        .bucket_id(bucket_id)
+                        .prefix((!prefix.is_empty()).then_some(prefix))
+                        .start_file_name(start_file_name.as_deref())
+                        .start_file_id(start_file_id.as_deref())
+                        .max_file_count(1000)
+                        .build(),
+                )
+                .await?;
+
+            files.extend(list.files);
+
+            match list.next_file_name {
+                Some(next) => (start_file_name, start_file_id) = (Some(next), list.next_file_id),
+                None => return Ok(files),
+            }
+        }
+    }
+
+    /// Compares the files under `local_root` with the files under `remote_prefix` and
+    /// computes the actions needed to sync them, without changing anything.
+    ///
+    /// `remote_prefix` is prepended to relative local paths as-is, so include a trailing `/`
+    /// to sync with a "folder". Remote files whose names are not safe local paths are ignored.
+    pub async fn sync_plan(
+        &self,
+        local_root: &Path,
+        remote_prefix: &str,
+        options: &SyncOptions<'_>,
+    ) -> Result<SyncPlan, B2Error> {
+        let filter = PathFilter::new(&options.include, &options.exclude)?;
+
+        let (local, failed) = match tokio::fs::try_exists(local_root).await? {
+            true => walk_dir(local_root, &filter).await?,
+            false if options.direction != SyncDirection::Upload => (Vec::new(), Vec::new()),
+            false => return Err(std::io::Error::from(std::io::ErrorKind::NotFound).into()),
+        };
+
+        let delete_versions = options.delete
+            && options.direction == SyncDirection::Upload
+            && options.remote_delete == RemoteDelete::AllVersions;
+
+        let mut remote: BTreeMap<String, RemoteFile> = BTreeMap::new();
+
+        for file in self.list_all_files(options.bucket_id, remote_prefix, delete_versions).await? {
+            let Some(relative) = local_relative(&file.file_name, remote_prefix) else {
+                continue;
+            };
+
+            if !filter.is_match(&relative) {
+                continue;
+            }
+
+            // versions are listed from newest to oldest
+            let entry = remote.entry(relative).or_insert(RemoteFile {
+                latest: None,
+                file_ids: Vec::new(),
+            });
+
+            match file.action {
+                Some(B2FileAction::Started | B2FileAction::Folder) => continue,
+                Some(B2FileAction::Hidden) => {}
+                _ if entry.file_ids.is_empty() => {
+                    entry.file_ids.push(file.file_id.clone());
+                    entry.latest = Some(file);
+                    continue;
+                }
+                _ => {}
+            }
+
+            entry.file_ids.push(file.file_id);
+        }
+
+        let mut local: BTreeMap<String, LocalFile> =
+            local.into_iter().map(|file| (file.relative.clone(), file)).collect();
+
+        let mut plan = SyncPlan {
+            actions: Vec::new(),
+            failed,
+        };
+
+        let relatives: Vec<String> = local.keys().chain(remote.keys()).cloned().collect();
+
+        for relative in relatives {
+            let local_file = local.remove(&relative);
+            let remote_file = remote.remove(&relative);
+
+            if local_file.is_none() && remote_file.is_none() {
+                continue; // already visited
+            }
+
+            let latest = remote_file.as_ref().and_then(|remote| remote.latest.as_ref());
+
+            let mut same = false;
+
+            if let (Some(local), Some(remote)) = (&local_file, latest) {
+                if local.metadata.len() == remote.content_length {
+                    same = match remote.known_sha1() {
+                        Some(sha1) if options.compare_sha1 => match hash_file(&local.path).await {
+                            Ok(local_sha1) => local_sha1.eq_ignore_ascii_case(sha1),
+                            Err(e) => {
+                                plan.failed.push((local.path.clone(), e));
+                                continue;
+                            }
+                        },
+                        _ => modified_millis(&local.metadata) == Some(remote_modified(remote)),
+                    };
+                }
+            }
+
+            let decision = decide(
+                options,
+                local_file.as_ref().map(|local| Side {
+                    modified: modified_millis(&local.metadata).unwrap_or(0),
+                }),
+                latest.map(|remote| Side {
+                    modified: remote_modified(remote),
+                }),
+                same,
+            );
+
+            let file_name = || SmolStr::from(format!("{remote_prefix}{relative}"));
+
+            plan.actions.push(match (decision, local_file, remote_file) {
+                (Decision::Upload, Some(local), _) => SyncAction::Upload {
+                    path: local.path,
+                    file_name: file_name(),
+                },
+                (Decision::Download, _, Some(RemoteFile { latest: Some(file), .. })) => SyncAction::Download {
+                    path: local_root.join(&relative),
+                    file: Box::new(file),
+                },
+                (Decision::DeleteLocal, _, _) => SyncAction::DeleteLocal {
+                    path: local_root.join(&relative),
+                },
+                (Decision::DeleteRemote, _, _) if !plan.failed.is_empty() => continue,
+                (Decision::DeleteRemote, _, Some(remote)) => match options.remote_delete {
+                    RemoteDelete::Hide => SyncAction::HideRemote { file_name: file_name() },
+                    RemoteDelete::AllVersions => SyncAction::DeleteRemote {
+                        file_name: file_name(),
+                        file_ids: remote.file_ids,
+                    },
+                },
+                // remaining versions of hidden files
+                (Decision::Nothing, None, Some(remote))
+                    if delete_versions && plan.failed.is_empty() && !remote.file_ids.is_empty() =>
+                {
+                    SyncAction::DeleteRemote {
+                        file_name: file_name(),
+                        file_ids: remote.file_ids,
+                    }
+                }
+                _ => continue,
+            });
+        }
+
+        Ok(plan)
+    }
+
+    /// Executes the actions of a [`SyncPlan`] with bounded concurrency.
+    ///
+    /// Failures are collected for each action in the returned [`SyncReport`].
+    pub async fn sync_execute(&self, plan: SyncPlan, options: &SyncOptions<'_>) -> SyncReport {
+        let max_simultaneous_files = match options.max_simultaneous_files {
+            0 => 4,
+            n => n,
+        };
+
+        let recommended_part_size = self.state.read().await.account.api.storage.recommended_part_size;
+
+        let pool = Pool::new(self.clone(), options.bucket_id, max_simultaneous_files);
+
+        let mut report = SyncReport {
+            completed: Vec::with_capacity(plan.actions.len()),
+            planned: Vec::new(),
+            failed: plan.failed.into_iter().map(|(path, e)| (SyncFailure::Path(path), e)).collect(),
+        };
+
+        let actions = stream::iter(plan.actions).map(|action| {
+            let pool = &pool;
+
+            async move {
+                let res = match action {
+                    SyncAction::Upload {
+                        ref path,
+                        ref file_name,
+                    } => {
+                        let info = NewFileFromPath::builder()
+                            .path(path)
+                            .file_name(file_name.as_str())
+                            .content_type(options.content_type)
+                            .max_simultaneous_uploads(options.max_simultaneous_uploads)
+                            .encryption(options.encryption.clone())
+                            .build();
+
+                        // large files get their own upload part URLs
+                        let res = match tokio::fs::metadata(path).await {
+                            Ok(metadata) if metadata.len() <= recommended_part_size => {
+                                match pool.get_pooled_upload_url().await {
+                                    Ok(mut url) => {
+                                        self.upload_from_path(&info, options.bucket_id, Some(&mut url)).await
+                                    }
+                                    Err(e) => Err(e),
+                                }
+                            }
+                            Ok(_) => self.upload_from_path(&info, options.bucket_id, None).await,
+                            Err(e) => Err(e.into()),
+                        };
+
+                        res.map(|_| ())
+                    }
+                    SyncAction::Download { ref file, ref path } => self.sync_download(file, path, options).await,
+                    SyncAction::HideRemote { ref file_name } => {
+                        self.hide_file(options.bucket_id, file_name).await.map(|_| ())
+                    }
+                    SyncAction::DeleteRemote {
+                        ref file_name,
+                        ref file_ids,
+                    } => {
+                        let mut res = Ok(());
+
+                        for file_id in file_ids {
+                            res = self.delete_file(file_id, file_name, false).await;
+
+                            if res.is_err() {
+                                break;
+                            }
+                        }
+
+                        res
+                    }
+                    SyncAction::DeleteLocal { ref path } => {
+                        tokio::fs::remove_file(path).await.map_err(B2Error::from)
+                    }
+                };
+
+                (action, res)
+            }
+        });
+
+        // Box the future to avoid bloating the stack too much, especially with large DEFAULT_BUF_SIZE
+        let mut actions = Box::pin(actions.buffer_unordered(max_simultaneous_files as usize));
+
+        while let Some((action, res)) = actions.next().await {
+            match res {
+                Ok(()) => report.completed.push(action),
+                Err(e) => report.failed.push((SyncFailure::Action(action), e)),
+            }
+        }
+
+        report
+    }
+
+    /// Syncs the files under `local_root` with the files under `remote_prefix`,
+    /// see [`Client::sync_plan`] and [`Client::sync_execute`].
+    ///
+    /// If [`SyncOptions::dry_run`] is set, the planned actions are returned in
+    /// [`SyncReport::planned`] without being executed.
+    pub async fn sync(
+        &self,
+        local_root: &Path,
+        remote_prefix: &str,
+        options: &SyncOptions<'_>,
+    ) -> Result<SyncReport, B2Error> {
+        let plan = self.sync_plan(local_root, remote_prefix, options).await?;
+
+        if options.dry_run {
+            return Ok(SyncReport {
+                completed: Vec::new(),
+                planned: plan.actions,
+                failed: plan.failed.into_iter().map(|(path, e)| (SyncFailure::Path(path), e)).collect(),
+            });
+        }
+
+        Ok(self.sync_execute(plan, options).await)
+    }
+
+    /// Downloads a file to a temporary file next to `path`, verifying its SHA1 hash if known,
+    /// then sets its modification time and moves it into place.
+    async fn sync_download(
+        &self,
+        file: &B2FileInfo,
+        path: &Path,
+        options: &SyncOptions<'_>,
+    ) -> Result<(), B2Error> {
+        let encryption = match options.encryption {
+            sse::ServerSideEncryption::Customer(ref key) => Some(key.clone()),
+            _ => None,
+        };
+
+        let mut download = self.download_file(DownloadFileBy::FileId(&file.file_id), None, encryption).await?;
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let mut tmp_name = path.file_name().ok_or(B2Error::MissingFileName)?.to_owned();
+        tmp_name.push(".b2sync");
+        let tmp_path = path.with_file_name(tmp_name);
+
+        let res = async {
+            let mut out = tokio::fs::File::create(&tmp_path).await?;
+            let mut sha1 = Sha1::new();
+
+            while let Some(chunk) = download.chunk().await? {
+                sha1.update(&chunk);
+                out.write_all(&chunk).await?;
+            }
+
+            let actual = hex::encode(sha1.finalize());
+
+            if let Some(expected) = file.known_sha1() {
+                if !expected.eq_ignore_ascii_case(&actual) {
+                    return Err(B2Error::Sha1Mismatch {
+                        expected: expected.to_owned(),
+                        actual,
+                    });
+                }
+            }
+
+            out.flush().await?;
+
+            let out = out.into_std().await;
+            let modified = UNIX_EPOCH + Duration::from_millis(remote_modified(file));
+
+            tokio::task::spawn_blocking(move || out.set_modified(modified))
+                .await
+                .map_err(|_| B2Error::Unknown)??;
+
+            tokio::fs::rename(&tmp_path, path).await?;
+
+            Ok(())
+        };
+
+        let res = res.await;
+
+        if res.is_err() {
+            _ = tokio::fs::remove_file(&tmp_path).await;
+        }
+
+        res
+    }
+}
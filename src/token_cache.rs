@@ -0,0 +1,167 @@
+//! Caching authorization tokens across clients and process restarts.
+//!
+//! Every call to `b2_authorize_account` is a Class C transaction, so short-lived processes can reuse
+//! a recent authorization with [`ClientBuilder::token_cache`](crate::ClientBuilder::token_cache).
+//!
+//! **NOTE**: Cached tokens grant the same access as the application key until they expire,
+//! so should be stored as carefully as the key itself.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use reqwest::header::HeaderValue;
+use smol_str::SmolStr;
+
+use crate::models::B2Authorized;
+
+/// The maximum age of a cached token before a new one is requested,
+/// as tokens are valid for at most 24 hours.
+pub const MAX_TOKEN_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Storage for serialized authorization tokens, keyed by application key ID.
+///
+/// Errors should be ignored by implementations, in which case a new token is requested as usual.
+pub trait TokenCache: Send + Sync + 'static {
+    /// Loads the serialized token stored for the given key ID, if any.
+    fn load(&self, key_id: &str) -> Option<Vec<u8>>;
+
+    /// Stores a serialized token for the given key ID, replacing any existing token.
+    fn store(&self, key_id: &str, token: &[u8]);
+}
+
+/// An authorization along with when it was obtained, as stored in a [`TokenCache`].
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct CachedToken {
+    /// The application key ID used to authorize.
+    pub key_id: SmolStr,
+
+    /// When the token was obtained, in milliseconds since the Unix epoch.
+    pub obtained_at: u64,
+
+    /// The response from `b2_authorize_account`.
+    pub account: B2Authorized,
+}
+
+pub(crate) fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+impl CachedToken {
+    /// Loads a token from the cache, only if it was obtained by the same key ID within [`MAX_TOKEN_AGE`],
+    /// the key has not expired, and the token can be sent as a header.
+    ///
+    /// Returns the authorization and when it was obtained.
+    pub fn load(cache: &dyn TokenCache, key_id: &str) -> Option<(B2Authorized, u64)> {
+        let token: CachedToken = serde_json::from_slice(&cache.load(key_id)?).ok()?;
+
+        let now = now_millis();
+
+        let fresh = token.key_id == key_id
+            && token.obtained_at <= now
+            && now - token.obtained_at < MAX_TOKEN_AGE.as_millis() as u64
+            && token.account.expiration.is_none_or(|expiration| expiration > now)
+            && HeaderValue::from_str(&token.account.auth_token).is_ok();
+
+        fresh.then_some((token.account, token.obtained_at))
+    }
+
+    /// Stores a newly obtained token in the cache.
+    pub fn store(cache: &dyn TokenCache, key_id: &str, account: &B2Authorized) {
+        #[derive(Serialize)]
+        struct CachedTokenRef<'a> {
+            key_id: &'a str,
+            obtained_at: u64,
+            account: &'a B2Authorized,
+        }
+
+        let token = CachedTokenRef {
+            key_id,
+            obtained_at: now_millis(),
+            account,
+        };
+
+        if let Ok(bytes) = serde_json::to_vec(&token) {
+            cache.store(key_id, &bytes);
+        }
+    }
+}
+
+/// A [`TokenCache`] that keeps tokens in memory, for sharing authorization between clients in one process.
+#[derive(Default, Debug)]
+pub struct MemoryTokenCache {
+    tokens: Mutex<HashMap<SmolStr, Vec<u8>>>,
+}
+
+impl MemoryTokenCache {
+    /// Creates a new empty cache.
+    pub fn new() -> Self {
+        MemoryTokenCache::default()
+    }
+}
+
+impl TokenCache for MemoryTokenCache {
+    fn load(&self, key_id: &str) -> Option<Vec<u8>> {
+        self.tokens.lock().unwrap_or_else(|e| e.into_inner()).get(key_id).cloned()
+    }
+
+    fn store(&self, key_id: &str, token: &[u8]) {
+        self.tokens.lock().unwrap_or_else(|e| e.into_inner()).insert(SmolStr::from(key_id), token.to_vec());
+    }
+}
+
+/// A [`TokenCache`] that keeps tokens in a directory, one file per key ID.
+///
+/// On Unix, token files are created readable only by the current user.
+#[derive(Debug, Clone)]
+pub struct FileTokenCache {
+    dir: PathBuf,
+}
+
+impl FileTokenCache {
+    /// Creates a cache storing tokens in the given directory, which is created if needed.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        FileTokenCache { dir: dir.into() }
+    }
+
+    fn path(&self, key_id: &str) -> Option<PathBuf> {
+        // key IDs are alphanumeric, but avoid escaping the directory regardless
+        if key_id.is_empty() || !key_id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-') {
+            return None;
+        }
+
+        Some(self.dir.join(format!("{key_id}.json")))
+    }
+}
+
+impl TokenCache for FileTokenCache {
+    fn load(&self, key_id: &str) -> Option<Vec<u8>> {
+        std::fs::read(self.path(key_id)?).ok()
+    }
+
+    fn store(&self, key_id: &str, token: &[u8]) {
+        use std::io::Write;
+
+        let Some(path) = self.path(key_id) else { return };
+
+        if std::fs::create_dir_all(&self.dir).is_err() {
+            return;
+        }
+
+        // write to a temporary file and rename, so concurrent processes never read a partial token
+        let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        let written = options.open(&tmp).and_then(|mut file| file.write_all(token));
+
+        if written.is_err() || std::fs::rename(&tmp, &path).is_err() {
+            _ = std::fs::remove_file(&tmp);
+        }
+    }
+}
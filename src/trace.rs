@@ -0,0 +1,95 @@
+//! `tracing` spans and events for B2 operations.
+//!
+//! Spans are opened around each operation by the [`Client`](crate::Client), and filled in by
+//! [`RequestBuilder::send`](crate::transport::RequestBuilder::send) from the request and response.
+
+use reqwest::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use tracing::field::Empty;
+use tracing::Span;
+
+/// Opens a span for a B2 operation, with fields recorded once the request is sent.
+///
+/// `otel.name` is set to the operation name, such as `b2_upload_part`, for OpenTelemetry exporters.
+pub(crate) fn op_span() -> Span {
+    tracing::info_span!(
+        "b2",
+        otel.name = Empty,
+        op = Empty,
+        bucket = Empty,
+        file_name = Empty,
+        part_number = Empty,
+        bytes_sent = Empty,
+        bytes_received = Empty,
+        attempt = Empty,
+        status = Empty,
+    )
+}
+
+fn header<'a, B>(req: &'a http::Request<B>, name: &str) -> Option<&'a str> {
+    req.headers().get(name).and_then(|value| value.to_str().ok())
+}
+
+/// Records the operation, bucket, file name, part number and request size into the current span.
+pub(crate) fn record_request(req: &http::Request<reqwest::Body>) {
+    let span = Span::current();
+
+    if span.is_disabled() {
+        return;
+    }
+
+    if let Some(op) = crate::transport::operation(req.uri()) {
+        span.record("otel.name", op);
+        span.record("op", op);
+    }
+
+    if let Some(file_name) = header(req, "x-bz-file-name") {
+        span.record("file_name", file_name);
+    }
+
+    if let Some(part_number) = header(req, "x-bz-part-number").and_then(|n| n.parse::<u64>().ok()) {
+        span.record("part_number", part_number);
+    }
+
+    if let Some(length) = header(req, CONTENT_LENGTH.as_str()).and_then(|n| n.parse::<u64>().ok()) {
+        span.record("bytes_sent", length);
+    }
+
+    if let Some(query) = req.uri().query() {
+        let pairs: Vec<(String, String)> = serde_urlencoded::from_str(query).unwrap_or_default();
+
+        for (key, value) in pairs {
+            record_field(&span, &key, &serde_json::Value::String(value));
+        }
+    }
+
+    // API calls have small JSON bodies, already in memory
+    let is_json = header(req, CONTENT_TYPE.as_str()).is_some_and(|ct| ct.starts_with("application/json"));
+
+    if let Some(body) = req.body().as_bytes().filter(|_| is_json) {
+        if let Ok(serde_json::Value::Object(map)) = serde_json::from_slice(body) {
+            for (key, value) in &map {
+                record_field(&span, key, value);
+            }
+        }
+    }
+}
+
+fn record_field(span: &Span, key: &str, value: &serde_json::Value) {
+    match (key, value) {
+        ("bucketId" | "bucketName", serde_json::Value::String(bucket)) => span.record("bucket", bucket.as_str()),
+        ("fileName", serde_json::Value::String(file_name)) => span.record("file_name", file_name.as_str()),
+        ("partNumber", serde_json::Value::Number(n)) => span.record("part_number", n.as_u64()),
+        _ => span,
+    };
+}
+
+/// Records the status and response size into the current span.
+pub(crate) fn record_response<B>(resp: &http::Response<B>) {
+    let span = Span::current();
+
+    span.record("status", resp.status().as_u16());
+
+    if let Some(length) = resp.headers().get(CONTENT_LENGTH).and_then(|n| n.to_str().ok()?.parse::<u64>().ok()) {
+        span.record("bytes_received", length);
+    }
+}
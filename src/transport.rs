@@ -0,0 +1,304 @@
+//! Pluggable HTTP transports for sending requests to B2.
+//!
+//! By default, requests are sent with [`reqwest`] through [`ReqwestTransport`]. Other HTTP clients,
+//! recording or replaying transports for tests, and middleware such as metrics or fault injection
+//! can be used by implementing [`HttpTransport`] and passing it to [`ClientBuilder::transport`](crate::ClientBuilder::transport).
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use futures_util::future::BoxFuture;
+use futures_util::TryStreamExt;
+use http_body_util::{combinators::BoxBody, BodyExt};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_LENGTH, CONTENT_TYPE};
+use reqwest::Method;
+
+use crate::limit::{Limiter, Throttled};
+use crate::metrics::{Metrics, RequestMetrics, TransactionClass};
+use crate::B2Error;
+
+/// Boxed error type for response bodies.
+pub type BoxError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// A streaming response body returned by an [`HttpTransport`].
+pub type ResponseBody = BoxBody<Bytes, BoxError>;
+
+/// Sends HTTP requests on behalf of a [`Client`](crate::Client).
+///
+/// The request body is a [`reqwest::Body`], which implements [`http_body::Body`](https://docs.rs/http-body)
+/// and so can be sent by other HTTP clients such as `hyper` directly.
+pub trait HttpTransport: Send + Sync + 'static {
+    /// Sends a request, returning the response once its headers have been received.
+    ///
+    /// Transports should honor a [`Timeout`] in the request's extensions, if any. Unsuccessful
+    /// status codes must be returned as responses, not errors, as they are parsed as B2 error messages.
+    fn send(
+        &self,
+        request: http::Request<reqwest::Body>,
+    ) -> BoxFuture<'_, Result<http::Response<ResponseBody>, B2Error>>;
+}
+
+impl<T: HttpTransport + ?Sized> HttpTransport for Arc<T> {
+    fn send(
+        &self,
+        request: http::Request<reqwest::Body>,
+    ) -> BoxFuture<'_, Result<http::Response<ResponseBody>, B2Error>> {
+        (**self).send(request)
+    }
+}
+
+/// The total timeout of a request, inserted into its extensions for API calls
+/// when [`ClientBuilder::api_timeout`](crate::ClientBuilder::api_timeout) is set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeout(pub Duration);
+
+/// The default [`HttpTransport`], sending requests with a [`reqwest::Client`].
+#[derive(Debug, Clone)]
+pub struct ReqwestTransport {
+    client: reqwest::Client,
+}
+
+impl ReqwestTransport {
+    /// Creates a transport sending requests with the given client.
+    pub fn new(client: reqwest::Client) -> Self {
+        ReqwestTransport { client }
+    }
+}
+
+impl HttpTransport for ReqwestTransport {
+    fn send(
+        &self,
+        request: http::Request<reqwest::Body>,
+    ) -> BoxFuture<'_, Result<http::Response<ResponseBody>, B2Error>> {
+        Box::pin(async move {
+            let timeout = request.extensions().get::<Timeout>().copied();
+
+            let mut request = reqwest::Request::try_from(request)?;
+
+            if let Some(Timeout(timeout)) = timeout {
+                *request.timeout_mut() = Some(timeout);
+            }
+
+            let resp = self.client.execute(request).await?;
+
+            Ok(http::Response::from(resp).map(|body| body.map_err(BoxError::from).boxed()))
+        })
+    }
+}
+
+/// Returns the name of the B2 operation a request is for, such as `b2_list_file_names` or `b2_upload_part`,
+/// from the path of its URL.
+pub fn operation(uri: &http::Uri) -> Option<&str> {
+    let (_, rest) = uri.path().split_once("/b2api/")?;
+
+    rest.split('/').find(|segment| segment.starts_with("b2_"))
+}
+
+fn content_length(headers: &HeaderMap) -> Option<u64> {
+    headers.get(CONTENT_LENGTH)?.to_str().ok()?.parse().ok()
+}
+
+/// Converts an error from a [`ResponseBody`] into the most specific [`B2Error`].
+pub(crate) fn body_error(e: BoxError) -> B2Error {
+    let e = match e.downcast::<reqwest::Error>() {
+        Ok(e) => return B2Error::ReqwestError(*e),
+        Err(e) => e,
+    };
+
+    match e.downcast::<std::io::Error>() {
+        Ok(e) => B2Error::IOError(*e),
+        Err(e) => B2Error::Transport(e),
+    }
+}
+
+/// Reads the next chunk of data from a [`ResponseBody`], skipping trailers, or `None` at the end.
+pub(crate) async fn next_chunk(body: &mut ResponseBody) -> Result<Option<Bytes>, B2Error> {
+    loop {
+        match body.frame().await {
+            Some(frame) => match frame.map_err(body_error)?.into_data() {
+                Ok(data) => return Ok(Some(data)),
+                Err(_trailers) => continue,
+            },
+            None => return Ok(None),
+        }
+    }
+}
+
+/// Builds a request to be sent through an [`HttpTransport`].
+pub(crate) struct RequestBuilder {
+    transport: Arc<dyn HttpTransport>,
+    metrics: Option<Arc<dyn Metrics>>,
+    limiter: Option<Arc<Limiter>>,
+    request: Result<http::Request<reqwest::Body>, B2Error>,
+}
+
+impl RequestBuilder {
+    pub fn new(transport: Arc<dyn HttpTransport>, method: Method, url: &str) -> Self {
+        RequestBuilder {
+            transport,
+            metrics: None,
+            limiter: None,
+            request: http::Request::builder()
+                .method(method)
+                .uri(url)
+                .body(reqwest::Body::default())
+                .map_err(|e| B2Error::Transport(e.into())),
+        }
+    }
+
+    pub fn metrics(mut self, metrics: Option<Arc<dyn Metrics>>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    pub fn limiter(mut self, limiter: Option<Arc<Limiter>>) -> Self {
+        self.limiter = limiter;
+        self
+    }
+
+    fn with(mut self, f: impl FnOnce(&mut http::Request<reqwest::Body>) -> Result<(), B2Error>) -> Self {
+        if let Ok(ref mut request) = self.request {
+            if let Err(e) = f(request) {
+                self.request = Err(e);
+            }
+        }
+
+        self
+    }
+
+    pub fn header(self, name: HeaderName, value: &HeaderValue) -> Self {
+        self.with(|request| {
+            request.headers_mut().insert(name, value.clone());
+            Ok(())
+        })
+    }
+
+    pub fn headers(self, headers: HeaderMap) -> Self {
+        self.with(|request| {
+            request.headers_mut().extend(headers);
+            Ok(())
+        })
+    }
+
+    pub fn query<T: serde::Serialize + ?Sized>(self, query: &T) -> Self {
+        self.with(|request| {
+            let query = serde_urlencoded::to_string(query).map_err(|e| B2Error::Transport(e.into()))?;
+
+            if query.is_empty() {
+                return Ok(());
+            }
+
+            let uri = request.uri().to_string();
+            let sep = if request.uri().query().is_some() { '&' } else { '?' };
+
+            *request.uri_mut() = format!("{uri}{sep}{query}")
+                .parse()
+                .map_err(|e: http::uri::InvalidUri| B2Error::Transport(e.into()))?;
+
+            Ok(())
+        })
+    }
+
+    pub fn json<T: serde::Serialize + ?Sized>(self, json: &T) -> Self {
+        self.with(|request| {
+            *request.body_mut() = reqwest::Body::from(serde_json::to_vec(json)?);
+
+            if !request.headers().contains_key(CONTENT_TYPE) {
+                request.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+            }
+
+            Ok(())
+        })
+    }
+
+    pub fn body(self, body: impl Into<reqwest::Body>) -> Self {
+        self.with(|request| {
+            *request.body_mut() = body.into();
+            Ok(())
+        })
+    }
+
+    pub fn map_body(self, f: impl FnOnce(reqwest::Body) -> reqwest::Body) -> Self {
+        self.with(|request| {
+            let body = std::mem::take(request.body_mut());
+            *request.body_mut() = f(body);
+            Ok(())
+        })
+    }
+
+    pub fn timeout(self, timeout: Duration) -> Self {
+        self.with(|request| {
+            request.extensions_mut().insert(Timeout(timeout));
+            Ok(())
+        })
+    }
+
+    pub async fn send(self) -> Result<http::Response<ResponseBody>, B2Error> {
+        let mut request = self.request?;
+
+        #[cfg(feature = "tracing")]
+        crate::trace::record_request(&request);
+
+        let operation = operation(request.uri()).unwrap_or("unknown").to_owned();
+        let class = TransactionClass::of(&operation);
+
+        let permit = match self.limiter {
+            Some(ref limiter) => limiter.acquire(class).await,
+            None => None,
+        };
+
+        // only the bodies of uploads and downloads are limited by bandwidth
+        let throttle = self.limiter.clone().filter(|limiter| {
+            limiter.limits_bandwidth()
+                && matches!(
+                    &*operation,
+                    "b2_upload_file" | "b2_upload_part" | "b2_download_file_by_id" | "b2_download_file_by_name"
+                )
+        });
+
+        if let Some(ref limiter) = throttle {
+            let limiter = limiter.clone();
+
+            request = request.map(|body| {
+                reqwest::Body::wrap_stream(Throttled::new(http_body_util::BodyDataStream::new(body), limiter))
+            });
+        }
+
+        // measured before sending, as the request is consumed
+        let bytes_sent = content_length(request.headers())
+            .or_else(|| request.body().as_bytes().map(|body| body.len() as u64))
+            .unwrap_or(0);
+
+        let start = Instant::now();
+        let mut resp = self.transport.send(request).await?;
+
+        // released once the response headers arrive, so a download being read never blocks other requests,
+        // such as the upload it is streamed into
+        drop(permit);
+
+        #[cfg(feature = "tracing")]
+        crate::trace::record_response(&resp);
+
+        if let Some(ref metrics) = self.metrics {
+            metrics.request(&RequestMetrics {
+                operation: &operation,
+                class,
+                status: resp.status().as_u16(),
+                bytes_sent,
+                bytes_received: content_length(resp.headers()).unwrap_or(0),
+                latency: start.elapsed(),
+            });
+        }
+
+        if let Some(limiter) = throttle {
+            resp = resp.map(|body| {
+                let stream = Throttled::new(http_body_util::BodyDataStream::new(body), limiter);
+
+                http_body_util::StreamBody::new(stream.map_ok(http_body::Frame::data)).boxed()
+            });
+        }
+
+        Ok(resp)
+    }
+}
@@ -1,7 +1,10 @@
-use reqwest::header::HeaderMap;
+use std::collections::HashMap;
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
 use smol_str::SmolStr;
 
 use crate::models::{self, capabilities::B2CapabilitiesStringSet};
+use crate::B2Error;
 
 /// Identifier for a file to download, either by its file ID or file name.
 ///
@@ -286,6 +289,61 @@ impl FileRetention {
     }
 }
 
+/// The SHA1 hash of content to be uploaded.
+///
+/// Can be created from a hex string with `From`/`Into`, otherwise defaults to [`ContentSha1::Compute`].
+///
+/// Used in [`NewFileInfo`] and [`NewPartInfo`].
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentSha1<'a> {
+    /// The SHA1 hash of the content as a hex string, known ahead of time.
+    Hex(&'a str),
+
+    /// The 40 hex digits of the SHA1 hash are appended to the end of the body by the caller,
+    /// using B2's `hex_digits_at_end` mode.
+    ///
+    /// The `content_length` should still be the length of the content itself,
+    /// without the trailing hex digits.
+    HexAtEnd,
+
+    /// The SHA1 hash will be computed by the client.
+    ///
+    /// For the `_bytes` upload methods, it is computed before uploading. For streamed bodies,
+    /// it is computed while streaming and appended to the end of the body as with [`ContentSha1::HexAtEnd`],
+    /// so the content only needs to be read once.
+    #[default]
+    Compute,
+}
+
+impl<'a> From<&'a str> for ContentSha1<'a> {
+    #[inline]
+    fn from(hex: &'a str) -> Self {
+        ContentSha1::Hex(hex)
+    }
+}
+
+impl<'a> From<&'a String> for ContentSha1<'a> {
+    #[inline]
+    fn from(hex: &'a String) -> Self {
+        ContentSha1::Hex(hex)
+    }
+}
+
+impl<'a> ContentSha1<'a> {
+    /// Returns the hex string if the SHA1 hash is known ahead of time.
+    pub const fn hex(&self) -> Option<&'a str> {
+        match *self {
+            ContentSha1::Hex(hex) => Some(hex),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the hex digits of the SHA1 hash will be sent at the end of the body.
+    pub const fn is_at_end(&self) -> bool {
+        !matches!(self, ContentSha1::Hex(_))
+    }
+}
+
 /// Info about a new whole file to be uploaded.
 ///
 /// See the documentation for [`NewFileInfo::builder`] for more information.
@@ -315,8 +373,12 @@ pub struct NewFileInfo<'a> {
     #[builder(default, setter(into))]
     pub content_type: Option<&'a str>,
 
-    /// The SHA1 hash of the file's contents as a hex string.
-    pub content_sha1: &'a str,
+    /// The SHA1 hash of the file's contents.
+    ///
+    /// Defaults to [`ContentSha1::Compute`], having the client hash the body as it is sent.
+    /// Set a [`ContentSha1::Hex`] if the hash is already known, to avoid hashing it again.
+    #[builder(default, setter(into))]
+    pub content_sha1: ContentSha1<'a>,
 
     /// The server-side encryption to use when uploading the file.
     #[builder(default, via_mutators)]
@@ -329,6 +391,13 @@ pub struct NewFileInfo<'a> {
     /// Whether to apply a legal hold to the file.
     #[builder(default)]
     pub legal_hold: Option<bool>,
+
+    /// Custom file info to store with the file, up to 10 entries.
+    ///
+    /// `src_last_modified_millis` is recognized by B2 and other tools as the
+    /// original modification time of the file, in milliseconds since the Unix epoch.
+    #[builder(default, setter(into))]
+    pub file_info: Option<&'a HashMap<SmolStr, SmolStr>>,
 }
 
 /// Info about a new large file to be uploaded.
@@ -372,6 +441,13 @@ pub struct NewLargeFileInfo<'a> {
     /// Whether to apply a legal hold to the file.
     #[builder(default)]
     pub legal_hold: Option<bool>,
+
+    /// Custom file info to store with the file, up to 10 entries.
+    ///
+    /// `src_last_modified_millis` is recognized by B2 and other tools as the
+    /// original modification time of the file, in milliseconds since the Unix epoch.
+    #[builder(default, setter(into))]
+    pub file_info: Option<&'a HashMap<SmolStr, SmolStr>>,
 }
 
 /// Info about a new part of a large file to be uploaded.
@@ -396,20 +472,146 @@ pub struct NewPartInfo<'a> {
     /// The length of the part in bytes.
     pub content_length: u64,
 
-    /// The SHA1 hash of the part's contents as a hex string.
-    pub content_sha1: &'a str,
+    /// The SHA1 hash of the part's contents.
+    ///
+    /// Defaults to [`ContentSha1::Compute`], having the client hash the body as it is sent.
+    /// Set a [`ContentSha1::Hex`] if the hash is already known, to avoid hashing it again.
+    #[builder(default, setter(into))]
+    pub content_sha1: ContentSha1<'a>,
 
     /// The server-side encryption to use when uploading the file.
     #[builder(default, via_mutators)]
     pub encryption: sse::ServerSideEncryption,
 }
 
+/// Whether to copy or replace the content type and file info of a file copied with [`CopyFile`].
+#[derive(Default, Debug, Clone, Copy)]
+pub enum MetadataDirective<'a> {
+    /// Copy the content type and file info from the source file.
+    #[default]
+    Copy,
+
+    /// Replace the content type and file info with new values.
+    Replace {
+        /// The MIME type of the new file.
+        content_type: &'a str,
+
+        /// Custom file info to store with the new file, up to 10 entries.
+        file_info: Option<&'a HashMap<SmolStr, SmolStr>>,
+    },
+}
+
+/// Parameters for copying an existing file on the server side.
+///
+/// Used in [`Client::copy_file`](crate::Client::copy_file). Files up to 5GB can be copied this way,
+/// larger files must be copied in parts with [`LargeFileUpload::copy_part`](crate::LargeFileUpload::copy_part).
+///
+/// See the documentation for [`CopyFile::builder`] for more information.
+#[derive(Debug, typed_builder::TypedBuilder)]
+#[builder(doc, mutators(
+    pub fn encryption(&mut self, encryption: impl Into<sse::ServerSideEncryption>) {
+        self.encryption = encryption.into();
+    }
+))]
+pub struct CopyFile<'a> {
+    /// The ID of the file to copy.
+    pub source_file_id: &'a str,
+
+    /// The ID of the bucket to copy the file into. If `None`, the bucket of the source file will be used.
+    ///
+    /// Must be in the same account as the source file.
+    #[builder(default, setter(into))]
+    pub destination_bucket_id: Option<&'a str>,
+
+    /// The name of the new file.
+    pub file_name: &'a str,
+
+    /// The inclusive range of bytes to copy. If `None`, the entire file will be copied.
+    #[builder(default, setter(into))]
+    pub range: Option<std::ops::RangeInclusive<u64>>,
+
+    /// Whether to copy or replace the content type and file info.
+    #[builder(default)]
+    pub metadata: MetadataDirective<'a>,
+
+    /// The file retention settings to apply to the new file.
+    #[builder(default, setter(into))]
+    pub retention: Option<FileRetention>,
+
+    /// Whether to apply a legal hold to the new file.
+    #[builder(default)]
+    pub legal_hold: Option<bool>,
+
+    /// The SSE-C key the source file is encrypted with, if any.
+    #[builder(default, setter(into))]
+    pub source_encryption: Option<sse::ServerSideEncryptionCustomer>,
+
+    /// The server-side encryption to use for the new file.
+    #[builder(default, via_mutators)]
+    pub encryption: sse::ServerSideEncryption,
+}
+
+/// Parameters for copying a range of an existing file as a part of a large file.
+///
+/// Used in [`LargeFileUpload::copy_part`](crate::LargeFileUpload::copy_part).
+///
+/// See the documentation for [`CopyPart::builder`] for more information.
+#[derive(Debug, typed_builder::TypedBuilder)]
+#[builder(doc, mutators(
+    pub fn encryption(&mut self, encryption: impl Into<sse::ServerSideEncryption>) {
+        self.encryption = encryption.into();
+    }
+))]
+pub struct CopyPart<'a> {
+    /// The ID of the file to copy from.
+    pub source_file_id: &'a str,
+
+    /// The part number of the new large file part.
+    #[builder(setter(into))]
+    pub part_number: std::num::NonZeroU32,
+
+    /// The inclusive range of bytes to copy. If `None`, the entire file will be copied.
+    #[builder(default, setter(into))]
+    pub range: Option<std::ops::RangeInclusive<u64>>,
+
+    /// The SSE-C key the source file is encrypted with, if any.
+    #[builder(default, setter(into))]
+    pub source_encryption: Option<sse::ServerSideEncryptionCustomer>,
+
+    /// The server-side encryption of the large file, which must match the encryption
+    /// given to [`Client::start_large_file`](crate::Client::start_large_file).
+    #[builder(default, via_mutators)]
+    pub encryption: sse::ServerSideEncryption,
+}
+
+/// Formats an inclusive byte range for the `range` parameter of the copy APIs.
+pub(crate) fn copy_range(range: &Option<std::ops::RangeInclusive<u64>>) -> Option<String> {
+    range.as_ref().map(|range| format!("bytes={}-{}", range.start(), range.end()))
+}
+
+impl ContentSha1<'_> {
+    /// Length of the hex-encoded SHA1 hash appended to the body in `hex_digits_at_end` mode.
+    const HEX_DIGITS_LENGTH: u64 = 40;
+
+    fn add_headers(&self, content_length: u64, headers: &mut HeaderMap) {
+        match *self {
+            ContentSha1::Hex(hex) => {
+                h!(headers."content-length" => &content_length.to_string());
+                h!(headers."x-bz-content-sha1" => hex);
+            }
+            ContentSha1::HexAtEnd | ContentSha1::Compute => {
+                h!(headers."content-length" => &(content_length + Self::HEX_DIGITS_LENGTH).to_string());
+                h!(headers."x-bz-content-sha1" => "hex_digits_at_end");
+            }
+        }
+    }
+}
+
 impl NewFileInfo<'_> {
-    pub(crate) fn add_headers(&self, headers: &mut HeaderMap) {
+    pub(crate) fn add_headers(&self, headers: &mut HeaderMap) -> Result<(), B2Error> {
         h!(headers."x-bz-file-name" => &self.file_name);
         h!(headers."content-type" => self.content_type.unwrap_or("application/octet-stream"));
-        h!(headers."content-length" => &self.content_length.to_string());
-        h!(headers."x-bz-content-sha1" => self.content_sha1);
+        self.content_sha1.add_headers(self.content_length, headers);
 
         if let Some(ref retention) = self.retention {
             if let Some(ref mode) = retention.mode {
@@ -425,15 +627,56 @@ impl NewFileInfo<'_> {
             h!(headers."x-bz-file-legal-hold" => if legal_hold { "on" } else { "off" });
         }
 
+        if let Some(file_info) = self.file_info {
+            add_file_info_headers(file_info, headers)?;
+        }
+
         self.encryption.add_headers(headers);
+
+        Ok(())
+    }
+}
+
+/// The maximum number of custom file info entries B2 allows on a file.
+pub(crate) const MAX_FILE_INFO: usize = 10;
+
+/// Adds custom file info as `X-Bz-Info-*` headers, with percent-encoded values.
+fn add_file_info_headers(file_info: &HashMap<SmolStr, SmolStr>, headers: &mut HeaderMap) -> Result<(), B2Error> {
+    use std::fmt::Write;
+
+    if file_info.len() > MAX_FILE_INFO {
+        return Err(B2Error::TooManyFileInfo(file_info.len()));
+    }
+
+    for (key, value) in file_info {
+        let mut encoded = String::with_capacity(value.len());
+
+        for &b in value.as_bytes() {
+            match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                    encoded.push(b as char)
+                }
+                _ => write!(encoded, "%{b:02X}").unwrap(),
+            }
+        }
+
+        let name = HeaderName::try_from(format!("x-bz-info-{}", key.to_ascii_lowercase()))
+            .map_err(|_| B2Error::InvalidFileInfoKey(key.clone()))?;
+
+        // only contains ASCII after percent-encoding
+        headers.insert(
+            name,
+            HeaderValue::from_str(&encoded).expect("Unable to use header value"),
+        );
     }
+
+    Ok(())
 }
 
 impl NewPartInfo<'_> {
     pub(crate) fn add_headers(&self, headers: &mut HeaderMap) {
         h!(headers."x-bz-part-number" => &self.part_number.to_string());
-        h!(headers."content-length" => &self.content_length.to_string());
-        h!(headers."x-bz-content-sha1" => self.content_sha1);
+        self.content_sha1.add_headers(self.content_length, headers);
 
         self.encryption.add_headers(headers);
     }
@@ -457,7 +700,10 @@ pub mod sse {
     use reqwest::header::HeaderMap;
 
     /// Server-Side Encryption (SSE) with a customer-provided key (SSE-C)
+    ///
+    /// Serialized with the `customerKey` and `customerKeyMd5` field names B2 expects in JSON bodies.
     #[derive(Debug, Clone, Serialize)]
+    #[serde(rename_all = "camelCase")]
     pub struct ServerSideEncryptionCustomer {
         /// The algorithm to use when encrypting/decrypting a file using SSE-C encryption.
         ///
@@ -465,9 +711,11 @@ pub mod sse {
         pub algorithm: Cow<'static, str>,
 
         /// The base64-encoded AES256 encryption key when encrypting/decrypting a file using SSE-C encryption.
+        #[serde(rename = "customerKey")]
         pub key: String,
 
         /// The base64-encoded MD5 digest of the [`key`](ServerSideEncryptionCustomer::key) when encrypting/decrypting a file using SSE-C encryption.
+        #[serde(rename = "customerKeyMd5")]
         pub key_md5: String,
     }
 